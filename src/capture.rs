@@ -0,0 +1,214 @@
+//! Capture-and-replay of the incoming draw-command stream, for reproducing a reported rendering
+//! glitch after the fact without the game running: [`CaptureWriter`] logs every message a client
+//! sends as newline-delimited JSON prefixed with a relative millisecond timestamp, and
+//! [`read_capture`]/[`replay`] feed a captured file back through the same [`Command`] pipeline
+//! `main`'s listener uses, honoring (or, in "fast" mode, ignoring) the recorded delays between
+//! messages.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::graphics_data::{EmptyGraphic, Graphic};
+use crate::Command;
+
+/// Either kind of message a client can send: a drawable (or delete-on-arrival) [`Graphic`], or a
+/// bare [`EmptyGraphic`] clearing an id outright.
+///
+/// Deserialization tries `Graphic` first, and it accepts a bare id/ttl line too (as a graphic with
+/// no drawable), so a message read back from a capture is always the `Graphic` variant; replaying
+/// treats the two variants alike.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CapturedMessage {
+    Graphic(Graphic),
+    EmptyGraphic(EmptyGraphic),
+}
+
+impl CapturedMessage {
+    fn into_graphic(self) -> Graphic {
+        match self {
+            CapturedMessage::Graphic(graphic) => graphic,
+            CapturedMessage::EmptyGraphic(EmptyGraphic { id, ttl }) => Graphic {
+                id,
+                ttl,
+                drawable: None,
+                snapshot: None,
+            },
+        }
+    }
+}
+
+/// Appends every [`CapturedMessage`] it's given to a file as `<elapsed_ms> <json>` lines, relative
+/// to when the writer was created.
+pub struct CaptureWriter {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+impl CaptureWriter {
+    pub fn create(path: &Path) -> eyre::Result<CaptureWriter> {
+        let file = File::create(path)
+            .wrap_err_with(|| format!("failed to create capture file {:?}", path))?;
+        Ok(CaptureWriter {
+            file: BufWriter::new(file),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, message: &CapturedMessage) -> eyre::Result<()> {
+        let elapsed_ms = self.start.elapsed().as_millis();
+        let json = serde_json::to_string(message).wrap_err("failed to serialize message")?;
+        writeln!(self.file, "{} {}", elapsed_ms, json)
+            .wrap_err("failed to append to capture file")?;
+        self.file.flush().wrap_err("failed to flush capture file")
+    }
+}
+
+/// One message read back out of a capture file, with `delay` being the time since the *previous*
+/// message (or since the start of the capture, for the first one) rather than an absolute
+/// timestamp, so a replayer can just `sleep(delay)` before sending each one in turn.
+pub struct RecordedMessage {
+    pub delay: Duration,
+    pub message: CapturedMessage,
+}
+
+/// Parses a capture file written by [`CaptureWriter`] into its messages and inter-message delays.
+pub fn read_capture(path: &Path) -> eyre::Result<Vec<RecordedMessage>> {
+    let file =
+        File::open(path).wrap_err_with(|| format!("failed to open capture file {:?}", path))?;
+    let mut recorded = Vec::new();
+    let mut previous_ms: u128 = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line.wrap_err("failed to read capture file")?;
+        let (timestamp, json) = line
+            .split_once(' ')
+            .ok_or_else(|| eyre::eyre!("malformed capture line {:?}", line))?;
+        let elapsed_ms: u128 = timestamp
+            .parse()
+            .wrap_err_with(|| format!("malformed timestamp {:?}", timestamp))?;
+        let message: CapturedMessage = serde_json::from_str(json)
+            .wrap_err_with(|| format!("malformed captured message {:?}", json))?;
+        recorded.push(RecordedMessage {
+            delay: Duration::from_millis((elapsed_ms - previous_ms) as u64),
+            message,
+        });
+        previous_ms = elapsed_ms;
+    }
+    Ok(recorded)
+}
+
+/// Replays a capture file into `tx` as [`Command`]s under the given `client_id`, honoring each
+/// message's recorded delay unless `fast` is set, in which case they're all sent back-to-back.
+pub async fn replay(
+    path: &Path,
+    client_id: usize,
+    fast: bool,
+    tx: &mpsc::Sender<Command>,
+) -> eyre::Result<()> {
+    for recorded in read_capture(path)? {
+        if !fast {
+            tokio::time::sleep(recorded.delay).await;
+        }
+        tx.send(Command {
+            client_id,
+            graphic: recorded.message.into_graphic(),
+        })
+        .await
+        .wrap_err("renderer task is gone")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A path under the system temp dir unique to this test process and `name`, so parallel test
+    /// runs don't clobber each other's capture files.
+    fn temp_capture_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "edmcoverlay-capture-test-{}-{}.txt",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn empty_graphic(id: &str, ttl: isize) -> EmptyGraphic {
+        EmptyGraphic {
+            id: id.to_owned(),
+            ttl,
+        }
+    }
+
+    #[test]
+    fn read_capture_computes_delays_relative_to_the_previous_message() {
+        let path = temp_capture_path("delays");
+        std::fs::write(
+            &path,
+            "0 {\"id\":\"a\",\"ttl\":0}\n\
+             100 {\"id\":\"b\",\"ttl\":0}\n\
+             250 {\"id\":\"c\",\"ttl\":0}\n",
+        )
+        .unwrap();
+        let recorded = read_capture(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let delays: Vec<u128> = recorded.iter().map(|r| r.delay.as_millis()).collect();
+        assert_eq!(delays, vec![0, 100, 150]);
+    }
+
+    #[test]
+    fn read_capture_round_trips_an_empty_graphic_message() {
+        let path = temp_capture_path("empty-graphic");
+        std::fs::write(&path, "0 {\"id\":\"cleared\",\"ttl\":0}\n").unwrap();
+        let recorded = read_capture(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recorded.len(), 1);
+        // A bare id/ttl line comes back as a `Graphic` with no drawable, i.e. a delete-on-arrival.
+        match &recorded[0].message {
+            CapturedMessage::Graphic(g) => {
+                assert_eq!(g.id, "cleared");
+                assert!(g.drawable.is_none());
+            }
+            CapturedMessage::EmptyGraphic(_) => panic!("expected a Graphic with no drawable"),
+        }
+    }
+
+    #[test]
+    fn read_capture_rejects_a_malformed_line() {
+        let path = temp_capture_path("malformed");
+        std::fs::write(&path, "not-a-timestamp-and-no-space\n").unwrap();
+        let result = read_capture(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn captured_message_into_graphic_converts_an_empty_graphic_to_a_delete_on_arrival_graphic() {
+        let graphic = CapturedMessage::EmptyGraphic(empty_graphic("x", 5)).into_graphic();
+        assert_eq!(graphic.id, "x");
+        assert_eq!(graphic.ttl, 5);
+        assert!(graphic.drawable.is_none());
+    }
+
+    #[test]
+    fn capture_writer_record_then_read_capture_round_trips() {
+        let path = temp_capture_path("round-trip");
+        let mut writer = CaptureWriter::create(&path).unwrap();
+        writer
+            .record(&CapturedMessage::EmptyGraphic(empty_graphic("a", 0)))
+            .unwrap();
+        writer
+            .record(&CapturedMessage::EmptyGraphic(empty_graphic("b", 0)))
+            .unwrap();
+        let recorded = read_capture(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recorded.len(), 2);
+    }
+}
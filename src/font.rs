@@ -0,0 +1,277 @@
+//! TrueType loading, text shaping via [`rustybuzz`], and a small software rasterizer for turning
+//! shaped glyphs into alpha-coverage bitmaps.
+//!
+//! Neither backend can draw arbitrary Unicode through core X11 bitmap fonts, so text rendering
+//! doesn't depend on the window system at all: this module shapes and rasterizes glyphs in pure
+//! Rust, and each [`Renderer`](crate::render::Renderer) just blits (or, for the Skia backend,
+//! draws) the resulting glyph run.
+
+use std::fs;
+use std::path::Path;
+
+use eyre::WrapErr;
+use ttf_parser::OutlineBuilder;
+
+/// An owned TrueType/OpenType font, ready to shape and rasterize text with.
+///
+/// The font data is leaked to get a `'static` borrow: fonts are loaded once at startup and live
+/// for the process's whole lifetime, so there's no real owner to hand the borrow back to.
+pub struct Font {
+    face: ttf_parser::Face<'static>,
+    rb_face: rustybuzz::Face<'static>,
+}
+
+impl Font {
+    #[tracing::instrument]
+    pub fn load(path: &Path) -> eyre::Result<Font> {
+        let data =
+            fs::read(path).wrap_err_with(|| format!("failed to read font file {:?}", path))?;
+        let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+        let face = ttf_parser::Face::parse(data, 0)
+            .wrap_err_with(|| format!("failed to parse font file {:?}", path))?;
+        let rb_face = rustybuzz::Face::from_slice(data, 0)
+            .ok_or_else(|| eyre::eyre!("rustybuzz could not parse font file {:?}", path))?;
+        Ok(Font { face, rb_face })
+    }
+
+    pub fn units_per_em(&self) -> u16 {
+        self.face.units_per_em()
+    }
+
+    pub fn ascender_px(&self, size_px: f32) -> f32 {
+        f32::from(self.face.ascender()) * size_px / f32::from(self.units_per_em())
+    }
+
+    pub fn descender_px(&self, size_px: f32) -> f32 {
+        -f32::from(self.face.descender()) * size_px / f32::from(self.units_per_em())
+    }
+
+    /// Shapes `text` with `rustybuzz` (handling kerning, ligatures and combining marks) and
+    /// returns each glyph's id, cluster (the byte offset of the codepoint it came from) and pen
+    /// offsets, already scaled to `size_px`.
+    pub fn shape(&self, text: &str, size_px: f32) -> Vec<ShapedGlyph> {
+        let mut buffer = rustybuzz::UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.guess_segment_properties();
+        let output = rustybuzz::shape(&self.rb_face, &[], buffer);
+
+        let scale = size_px / f32::from(self.units_per_em());
+        output
+            .glyph_infos()
+            .iter()
+            .zip(output.glyph_positions())
+            .map(|(info, pos)| ShapedGlyph {
+                glyph_id: info.glyph_id as u16,
+                cluster: info.cluster as usize,
+                x_advance: pos.x_advance as f32 * scale,
+                y_advance: pos.y_advance as f32 * scale,
+                x_offset: pos.x_offset as f32 * scale,
+                y_offset: pos.y_offset as f32 * scale,
+            })
+            .collect()
+    }
+
+    /// Rasterizes `glyph_id` at `size_px` into an 8-bit alpha coverage bitmap, plus the offset
+    /// from the pen position to the bitmap's top-left corner (Y grows downward, matching a
+    /// window's pixel buffer rather than font units).
+    ///
+    /// Falls back to `None` for glyphs with no outline (space, the notdef glyph on an empty
+    /// face, etc); callers should just advance the pen and draw nothing.
+    pub fn rasterize(&self, glyph_id: u16, size_px: f32) -> Option<RasterizedGlyph> {
+        let scale = size_px / f32::from(self.units_per_em());
+        let mut collector = OutlineCollector::new(scale);
+        let bbox = self
+            .face
+            .outline_glyph(ttf_parser::GlyphId(glyph_id), &mut collector)?;
+        if collector.contours.is_empty() {
+            return None;
+        }
+
+        // Font space is Y-up; flip to the Y-down bitmap space the renderers draw in.
+        let x_min = (f32::from(bbox.x_min) * scale).floor() as i32;
+        let x_max = (f32::from(bbox.x_max) * scale).ceil() as i32;
+        let y_min = -(f32::from(bbox.y_max) * scale).ceil() as i32;
+        let y_max = -(f32::from(bbox.y_min) * scale).floor() as i32;
+        let width = (x_max - x_min).max(1) as u32;
+        let height = (y_max - y_min).max(1) as u32;
+
+        let mut coverage = vec![0u8; (width * height) as usize];
+        rasterize_scanline(
+            &collector.contours,
+            x_min,
+            y_min,
+            width,
+            height,
+            &mut coverage,
+        );
+
+        Some(RasterizedGlyph {
+            width,
+            height,
+            x_offset: x_min,
+            y_offset: y_min,
+            coverage,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShapedGlyph {
+    pub glyph_id: u16,
+    pub cluster: usize,
+    pub x_advance: f32,
+    pub y_advance: f32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+}
+
+/// An 8-bit alpha coverage bitmap for a single glyph, positioned relative to the pen.
+pub struct RasterizedGlyph {
+    pub width: u32,
+    pub height: u32,
+    /// Offset from the pen position to the bitmap's top-left corner, in Y-down pixel space.
+    pub x_offset: i32,
+    pub y_offset: i32,
+    /// Row-major, `width * height` bytes, one coverage value (0 = transparent, 255 = opaque) per
+    /// pixel.
+    pub coverage: Vec<u8>,
+}
+
+/// A single `(x, y)` vertex of a flattened glyph contour, in Y-down pixel space.
+type Vertex = (f32, f32);
+
+/// Collects a glyph's outline as flattened line segments, converting quadratic/cubic curves to
+/// short runs of line segments rather than rasterizing beziers directly.
+struct OutlineCollector {
+    scale: f32,
+    contours: Vec<Vec<Vertex>>,
+    current: Vec<Vertex>,
+    cursor: Vertex,
+}
+
+/// How many line segments a curve is flattened into; plenty for overlay-sized text.
+const CURVE_STEPS: usize = 8;
+
+impl OutlineCollector {
+    fn new(scale: f32) -> Self {
+        OutlineCollector {
+            scale,
+            contours: Vec::new(),
+            current: Vec::new(),
+            cursor: (0.0, 0.0),
+        }
+    }
+
+    fn point(&self, x: f32, y: f32) -> Vertex {
+        (x * self.scale, -y * self.scale)
+    }
+
+    /// Pushes whatever contour is in progress (if any) onto `contours`, leaving `current` empty
+    /// for the next one.
+    fn finish_contour(&mut self) {
+        if !self.current.is_empty() {
+            self.contours.push(std::mem::take(&mut self.current));
+        }
+    }
+}
+
+impl OutlineBuilder for OutlineCollector {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.finish_contour();
+        self.cursor = self.point(x, y);
+        self.current.push(self.cursor);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.cursor = self.point(x, y);
+        self.current.push(self.cursor);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let p0 = self.cursor;
+        let p1 = self.point(x1, y1);
+        let p2 = self.point(x, y);
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let mt = 1.0 - t;
+            let vx = mt * mt * p0.0 + 2.0 * mt * t * p1.0 + t * t * p2.0;
+            let vy = mt * mt * p0.1 + 2.0 * mt * t * p1.1 + t * t * p2.1;
+            self.current.push((vx, vy));
+        }
+        self.cursor = p2;
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let p0 = self.cursor;
+        let p1 = self.point(x1, y1);
+        let p2 = self.point(x2, y2);
+        let p3 = self.point(x, y);
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let mt = 1.0 - t;
+            let vx = mt * mt * mt * p0.0
+                + 3.0 * mt * mt * t * p1.0
+                + 3.0 * mt * t * t * p2.0
+                + t * t * t * p3.0;
+            let vy = mt * mt * mt * p0.1
+                + 3.0 * mt * mt * t * p1.1
+                + 3.0 * mt * t * t * p2.1
+                + t * t * t * p3.1;
+            self.current.push((vx, vy));
+        }
+        self.cursor = p3;
+    }
+
+    fn close(&mut self) {
+        if let Some(&first) = self.current.first() {
+            self.current.push(first);
+        }
+        self.finish_contour();
+    }
+}
+
+/// Fills `contours` with the nonzero winding rule into `coverage`, one sample per pixel centre.
+/// Not anti-aliased; plenty crisp enough for HUD-sized overlay text and much simpler than a
+/// supersampled rasterizer.
+fn rasterize_scanline(
+    contours: &[Vec<Vertex>],
+    x_min: i32,
+    y_min: i32,
+    width: u32,
+    height: u32,
+    coverage: &mut [u8],
+) {
+    for row in 0..height {
+        let y = y_min as f32 + row as f32 + 0.5;
+        let mut crossings: Vec<(f32, i32)> = Vec::new();
+        for contour in contours {
+            for window in contour.windows(2) {
+                let (x0, y0) = window[0];
+                let (x1, y1) = window[1];
+                if (y0 <= y && y1 > y) || (y1 <= y && y0 > y) {
+                    let t = (y - y0) / (y1 - y0);
+                    let x = x0 + t * (x1 - x0);
+                    let winding = if y1 > y0 { 1 } else { -1 };
+                    crossings.push((x, winding));
+                }
+            }
+        }
+        crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let mut winding_number = 0;
+        let mut iter = crossings.into_iter().peekable();
+        for col in 0..width {
+            let x = x_min as f32 + col as f32 + 0.5;
+            while let Some(&(cx, w)) = iter.peek() {
+                if cx > x {
+                    break;
+                }
+                winding_number += w;
+                iter.next();
+            }
+            if winding_number != 0 {
+                coverage[(row * width + col) as usize] = 255;
+            }
+        }
+    }
+}
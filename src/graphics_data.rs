@@ -10,61 +10,97 @@ pub struct Color {
     pub red: u8,
     pub green: u8,
     pub blue: u8,
+    pub alpha: u8,
 }
 
+/// Common named colors, resolved case-sensitively before falling back to hex parsing.
+const NAMED_COLORS: &[(&str, u8, u8, u8)] = &[
+    ("red", 255, 0, 0),
+    ("green", 0, 255, 0),
+    ("yellow", 255, 255, 0),
+    ("blue", 0, 0, 255),
+    ("black", 0, 0, 0),
+    ("white", 255, 255, 255),
+    ("teal", 0, 255, 255),
+    ("cyan", 0, 255, 255),
+    ("magenta", 255, 0, 255),
+    ("dark_gray", 64, 64, 64),
+    ("light_gray", 192, 192, 192),
+    ("orange", 255, 165, 0),
+    ("purple", 128, 0, 128),
+];
+
 impl TryFrom<&str> for Color {
     type Error = eyre::Error;
 
     fn try_from(s: &str) -> eyre::Result<Color> {
         lazy_static! {
-            static ref HEX_REGEX: Regex =
-                Regex::new(r"^#([0-9a-fA-F]{2})([0-9a-fA-F]{2})([0-9a-fA-F]{2})$").unwrap();
+            // Longest alternatives first, since regex alternation takes the first that matches.
+            static ref HEX_REGEX: Regex = Regex::new(concat!(
+                r"^#(?:",
+                r"([0-9a-fA-F]{2})([0-9a-fA-F]{2})([0-9a-fA-F]{2})([0-9a-fA-F]{2})",
+                r"|([0-9a-fA-F]{2})([0-9a-fA-F]{2})([0-9a-fA-F]{2})",
+                r"|([0-9a-fA-F])([0-9a-fA-F])([0-9a-fA-F])([0-9a-fA-F])",
+                r"|([0-9a-fA-F])([0-9a-fA-F])([0-9a-fA-F])",
+                r")$",
+            ))
+            .unwrap();
         }
-        match s {
-            "red" => {
-                return Ok(Color {
-                    red: 255,
-                    green: 0,
-                    blue: 0,
-                })
-            }
-            "green" => {
-                return Ok(Color {
-                    red: 0,
-                    green: 255,
-                    blue: 0,
-                })
-            }
-            "yellow" => {
-                return Ok(Color {
-                    red: 255,
-                    green: 255,
-                    blue: 0,
-                })
-            }
-            "blue" => {
-                return Ok(Color {
-                    red: 0,
-                    green: 0,
-                    blue: 255,
-                })
-            }
-            "black" => {
-                return Ok(Color {
-                    red: 0,
-                    green: 0,
-                    blue: 0,
-                })
-            }
-            _ => {}
+        if let Some((_, red, green, blue)) = NAMED_COLORS.iter().find(|(name, ..)| *name == s) {
+            return Ok(Color {
+                red: *red,
+                green: *green,
+                blue: *blue,
+                alpha: 255,
+            });
         }
-        match HEX_REGEX.captures(s) {
-            Some(captures) => Ok(Color {
-                red: u8::from_str_radix(&captures[1], 16).unwrap(),
-                green: u8::from_str_radix(&captures[2], 16).unwrap(),
-                blue: u8::from_str_radix(&captures[3], 16).unwrap(),
-            }),
-            None => Err(eyre::eyre!("")),
+        let captures = HEX_REGEX.captures(s).ok_or_else(|| {
+            eyre::eyre!(
+                "{:?} isn't a named color or #rgb/#rgba/#rrggbb/#rrggbbaa hex code",
+                s
+            )
+        })?;
+        let component = |group: usize| -> u8 {
+            let digits = &captures[group];
+            let value = u8::from_str_radix(digits, 16).unwrap();
+            if digits.len() == 1 {
+                value * 0x11
+            } else {
+                value
+            }
+        };
+        if captures.get(1).is_some() {
+            // #rrggbbaa
+            Ok(Color {
+                red: component(1),
+                green: component(2),
+                blue: component(3),
+                alpha: component(4),
+            })
+        } else if captures.get(5).is_some() {
+            // #rrggbb
+            Ok(Color {
+                red: component(5),
+                green: component(6),
+                blue: component(7),
+                alpha: 255,
+            })
+        } else if captures.get(8).is_some() {
+            // #rgba
+            Ok(Color {
+                red: component(8),
+                green: component(9),
+                blue: component(10),
+                alpha: component(11),
+            })
+        } else {
+            // #rgb
+            Ok(Color {
+                red: component(12),
+                green: component(13),
+                blue: component(14),
+                alpha: 255,
+            })
         }
     }
 }
@@ -79,7 +115,78 @@ impl TryFrom<String> for Color {
 
 impl From<Color> for String {
     fn from(c: Color) -> String {
-        format!("#{:02x}{:02x}{:02x}", c.red, c.green, c.blue)
+        if c.alpha == 255 {
+            format!("#{:02x}{:02x}{:02x}", c.red, c.green, c.blue)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", c.red, c.green, c.blue, c.alpha)
+        }
+    }
+}
+
+#[cfg(test)]
+mod color_tests {
+    use super::Color;
+    use std::convert::TryFrom;
+
+    fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Color {
+        Color {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    #[test]
+    fn parses_named_colors() {
+        let c = Color::try_from("teal").unwrap();
+        assert_eq!((c.red, c.green, c.blue, c.alpha), (0, 255, 255, 255));
+    }
+
+    #[test]
+    fn rejects_an_unknown_name() {
+        assert!(Color::try_from("not_a_color").is_err());
+    }
+
+    #[test]
+    fn parses_rrggbb() {
+        let c = Color::try_from("#1a2b3c").unwrap();
+        assert_eq!((c.red, c.green, c.blue, c.alpha), (0x1a, 0x2b, 0x3c, 255));
+    }
+
+    #[test]
+    fn parses_rrggbbaa() {
+        let c = Color::try_from("#1a2b3c80").unwrap();
+        assert_eq!((c.red, c.green, c.blue, c.alpha), (0x1a, 0x2b, 0x3c, 0x80));
+    }
+
+    #[test]
+    fn parses_shorthand_rgb_by_doubling_each_digit() {
+        let c = Color::try_from("#abc").unwrap();
+        assert_eq!((c.red, c.green, c.blue, c.alpha), (0xaa, 0xbb, 0xcc, 255));
+    }
+
+    #[test]
+    fn parses_shorthand_rgba() {
+        let c = Color::try_from("#abcd").unwrap();
+        assert_eq!((c.red, c.green, c.blue, c.alpha), (0xaa, 0xbb, 0xcc, 0xdd));
+    }
+
+    #[test]
+    fn rejects_malformed_hex() {
+        assert!(Color::try_from("#12345").is_err());
+    }
+
+    #[test]
+    fn opaque_color_round_trips_as_rrggbb() {
+        let s: String = rgba(0x1a, 0x2b, 0x3c, 255).into();
+        assert_eq!(s, "#1a2b3c");
+    }
+
+    #[test]
+    fn translucent_color_round_trips_as_rrggbbaa() {
+        let s: String = rgba(0x1a, 0x2b, 0x3c, 0x80).into();
+        assert_eq!(s, "#1a2b3c80");
     }
 }
 
@@ -97,22 +204,25 @@ impl Default for Size {
     }
 }
 
-// TODO: does this need to be public?
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
-enum Marker {
+pub enum Marker {
     #[serde(rename = "circle")]
     Circle,
     #[serde(rename = "cross")]
     Cross,
+    #[serde(rename = "square")]
+    Square,
+    #[serde(rename = "triangle")]
+    Triangle,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct VectorElement {
     pub x: usize,
     pub y: usize,
-    marker: Marker,
-    color: Color,
-    text: String,
+    pub marker: Marker,
+    pub color: Color,
+    pub text: String,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
@@ -125,8 +235,23 @@ pub enum ShapeVect {
     #[serde(rename = "vect")]
     Vect,
 }
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum ShapeCircle {
+    #[serde(rename = "circle")]
+    Circle,
+}
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum ShapeEllipse {
+    #[serde(rename = "ellipse")]
+    Ellipse,
+}
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum ShapeLine {
+    #[serde(rename = "line")]
+    Line,
+}
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum Drawable {
     Rectangle {
@@ -147,11 +272,47 @@ pub enum Drawable {
         // ttl: isize,
         vector: Vec<VectorElement>,
     },
+    Circle {
+        // id: String,
+        shape: ShapeCircle,
+        x: usize,
+        y: usize,
+        radius: usize,
+        fill: Color,
+        color: Color,
+        // ttl: isize,
+    },
+    Ellipse {
+        // id: String,
+        shape: ShapeEllipse,
+        x: usize,
+        y: usize,
+        rx: usize,
+        ry: usize,
+        fill: Color,
+        color: Color,
+        // ttl: isize,
+    },
+    Line {
+        // id: String,
+        shape: ShapeLine,
+        x1: usize,
+        y1: usize,
+        x2: usize,
+        y2: usize,
+        width: usize,
+        color: Color,
+        // ttl: isize,
+    },
     Text {
         // id: String,
         text: String,
         #[serde(default)]
         size: Size,
+        /// Pixel size to shape and rasterize the text at; overrides [`Size::default_px`] when
+        /// given, so clients aren't limited to two hardcoded sizes. `size` still picks the font file.
+        #[serde(default)]
+        size_px: Option<f32>,
         color: Color,
         x: usize,
         y: usize,
@@ -159,13 +320,29 @@ pub enum Drawable {
     },
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl Size {
+    /// The pixel size a `Drawable::Text` with this preset `size` and no explicit `size_px` is
+    /// shaped and rasterized at.
+    pub fn default_px(self) -> f32 {
+        match self {
+            Size::Normal => 15.0,
+            Size::Large => 24.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Graphic {
     #[serde(deserialize_with = "serde_aux::field_attributes::deserialize_string_from_number")]
     pub id: String,
     pub ttl: isize,
     #[serde(flatten)]
     pub drawable: Option<Drawable>,
+    /// When present, this isn't a drawable at all: it's a request to write the current frame out
+    /// at this path, as a PNG (only the `--headless` backend can satisfy this) or, if the path
+    /// ends in `.svg`, as an SVG document (works on every backend).
+    #[serde(default)]
+    pub snapshot: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
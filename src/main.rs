@@ -1,26 +1,51 @@
 #![allow(dead_code)]
 
+mod capture;
+mod font;
 mod graphics_data;
+mod monitor;
+mod render;
+mod store;
+mod svg;
 mod x11;
 
 use std::collections::HashMap;
-use std::convert::{TryFrom, TryInto};
-use std::ffi::CString;
-use std::mem::MaybeUninit;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
+use std::str::FromStr;
 
-use eyre::{bail, eyre, WrapErr};
-use lazy_static::lazy_static;
-use regex::Regex;
+use eyre::{eyre, WrapErr};
 use structopt::StructOpt;
+use tokio::io::unix::AsyncFd;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::net::TcpListener;
 use tokio::sync::mpsc;
-use tracing::{debug, error, event, info, info_span, instrument, warn, Level};
+use tracing::{debug, info, info_span, instrument, warn};
 use tracing_error::ErrorLayer;
 use tracing_futures::Instrument;
 use tracing_subscriber::prelude::*;
 
 use graphics_data::{Drawable, Graphic, Size};
+use render::{Geometry, Rect, Renderer, X11Renderer};
+
+/// Which surface `renderer` draws into.
+#[derive(Clone, Copy, Debug)]
+enum Backend {
+    X11,
+    Skia,
+}
+
+impl FromStr for Backend {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> eyre::Result<Backend> {
+        match s {
+            "x11" => Ok(Backend::X11),
+            "skia" => Ok(Backend::Skia),
+            _ => Err(eyre!("unknown backend {:?} (expected x11 or skia)", s)),
+        }
+    }
+}
 
 #[derive(Clone, Debug, StructOpt)]
 #[structopt(name = "edmcoverlay")]
@@ -37,474 +62,265 @@ struct Opt {
     /// Height of overlay
     #[structopt(name = "HEIGHT")]
     height: u32,
+    /// Rendering backend to draw with
+    #[structopt(long, default_value = "x11", possible_values = &["x11", "skia"])]
+    backend: Backend,
+    /// TrueType/OpenType font file used for `"large"`-size text
+    #[structopt(
+        long,
+        default_value = "/usr/share/fonts/truetype/dejavu/DejaVuSans-Bold.ttf"
+    )]
+    title_font: PathBuf,
+    /// TrueType/OpenType font file used for normal-size text
+    #[structopt(
+        long,
+        default_value = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf"
+    )]
+    body_font: PathBuf,
+    /// Draw into an offscreen buffer instead of a real window; no X server is needed, and a
+    /// `Graphic` with a `snapshot` path can be sent to dump the current frame as a PNG
+    #[structopt(long)]
+    headless: bool,
+    /// Record every incoming draw command to this file, for replaying a reported rendering
+    /// glitch later with `--replay` instead of needing the game running
+    #[structopt(long)]
+    capture: Option<PathBuf>,
+    /// Replay a file previously written with `--capture` into the renderer at startup
+    #[structopt(long)]
+    replay: Option<PathBuf>,
+    /// When replaying, send every recorded message immediately instead of honoring the delays
+    /// between them
+    #[structopt(long)]
+    replay_fast: bool,
+    /// Position and size the overlay to cover this RandR output, by name, instead of the
+    /// X/Y/WIDTH/HEIGHT given above
+    #[structopt(long, conflicts_with = "auto-monitor")]
+    monitor: Option<String>,
+    /// Position and size the overlay to cover whichever monitor RandR considers primary,
+    /// instead of the X/Y/WIDTH/HEIGHT given above
+    #[structopt(long)]
+    auto_monitor: bool,
+    /// Follow this external (usually the game's) window as it's dragged or resized, keeping the
+    /// overlay glued to it; decimal X11 window id (e.g. from `xdotool getactivewindow`), x11
+    /// backend only
+    #[structopt(long)]
+    track_window: Option<u32>,
 }
 
+/// `client_id` reserved for commands fed in by `--replay`, distinct from any id `listener`
+/// assigns a real connection (which starts at 1 and counts up).
+const REPLAY_CLIENT_ID: usize = usize::MAX;
+
 #[derive(Debug)]
 struct Command {
     client_id: usize,
     graphic: Graphic,
 }
 
-#[derive(Debug)]
-struct Config {
-    x_position: i32,
-    y_position: i32,
-    width: u32,
-    height: u32,
-    title_font: Option<*mut x11::XFontStruct>,
-    body_font: Option<*mut x11::XFontStruct>,
+/// Lets a bare [`RawFd`] be handed to [`AsyncFd`], which wants an owner to poll the readiness of;
+/// the X connection it's borrowed from (inside `renderer: Box<dyn Renderer>`) is the real owner.
+struct BorrowedXConnFd(RawFd);
+
+impl AsRawFd for BorrowedXConnFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
 }
-// TODO: safety
-unsafe impl Send for Config {}
 
 const FPS: u32 = 1;
 
-fn scale_w(x: usize, width: u32) -> usize {
-    x * width as usize / 1280
-}
-fn scale_h(y: usize, height: u32) -> usize {
-    y * height as usize / 1024
-}
-fn scale_x(x: usize, width: u32) -> usize {
-    scale_w(x, width) + 20
-}
-fn scale_y(y: usize, height: u32) -> usize {
-    scale_h(y, height) + 40
+/// Builds the flat `(client_id, id) -> Graphic` view the [`Renderer`] trait draws from, from
+/// whatever `store` currently considers live.
+fn snapshot_graphics(store: &store::GraphicStore) -> HashMap<(usize, String), Option<Graphic>> {
+    store
+        .active()
+        .map(|(key, graphic)| (key.clone(), Some(graphic.clone())))
+        .collect()
 }
 
-// TODO: enable once https://github.com/tokio-rs/tracing/issues/1318 is fixed
-#[instrument(skip(display, window))]
-fn do_redraw(
-    config: &Config,
-    graphics: &HashMap<(usize, String), Option<Graphic>>,
-    expired: &[Graphic],
-    display: &x11::Display,
-    window: x11::Window,
-) -> eyre::Result<()> {
-    event!(
-        Level::TRACE,
-        ?graphics,
-        "redrawing {} graphics",
-        graphics.len()
-    );
-    let gc = unsafe { x11::ffi::XCreateGC(**display, window, 0, std::ptr::null_mut()) };
-    lazy_static! {
-        static ref HEX_REGEX: Regex =
-            Regex::new(r"^#([0-9a-fA-F]{2})([0-9a-fA-F]{2})([0-9a-fA-F]{2})$").unwrap();
-    }
-    unsafe {
-        x11::ffi::XSetForeground(
-            **display,
-            gc,
-            x11::XColor::from_rgba(**display, x11::ffi::XDefaultScreen(**display), 0, 0, 0, 0)
-                .pixel,
-        );
-    }
-    for graphic in expired.iter() {
-        unsafe {
-            match &graphic.drawable.as_ref().unwrap() {
-                Drawable::Rectangle { x, y, w, h, .. } => {
-                    x11::ffi::XFillRectangle(
-                        **display,
-                        window,
-                        gc,
-                        scale_x(*x, config.width) as i32,
-                        scale_y(*y, config.height) as i32,
-                        scale_w(*w, config.width) as u32,
-                        scale_h(*h, config.height) as u32,
-                    );
-                }
-                Drawable::Vector { vector, .. } => {
-                    let (xmin, xmax, ymin, ymax) = vector.iter().fold((0, 0, 0, 0), |acc, val| {
-                        (
-                            val.x.min(acc.0),
-                            val.x.max(acc.1),
-                            val.y.min(acc.2),
-                            val.y.max(acc.3),
-                        )
-                    });
-                    x11::ffi::XFillRectangle(
-                        **display,
-                        window,
-                        gc,
-                        scale_x(xmin, config.width) as i32,
-                        scale_y(ymin, config.height) as i32,
-                        scale_w(xmax - xmin, config.width) as u32,
-                        scale_h(ymax - ymin, config.height) as u32,
-                    );
-                }
-                Drawable::Text {
-                    text, size, x, y, ..
-                } => {
-                    let font = match size {
-                        Size::Normal => config.body_font.unwrap(),
-                        Size::Large => config.title_font.unwrap(),
-                    };
-                    let mut direction_return = 0;
-                    let mut font_ascent_return = 0;
-                    let mut font_descent_return = 0;
-                    let mut overall_return = std::mem::MaybeUninit::<x11::XCharStruct>::uninit();
-                    let s = CString::new(AsRef::<str>::as_ref(text))?;
-                    let b = s.as_bytes();
-                    if x11::ffi::XTextExtents(
-                        font,
-                        b.as_ptr() as *const i8,
-                        b.len() as i32,
-                        &mut direction_return,
-                        &mut font_ascent_return,
-                        &mut font_descent_return,
-                        overall_return.as_mut_ptr(),
-                    ) == 0
-                    {
-                        let overall_return = overall_return.assume_init();
-                        x11::ffi::XFillRectangle(
-                            **display,
-                            window,
-                            gc,
-                            scale_x(*x, config.width) as i32 + overall_return.lbearing as i32,
-                            scale_y(*y, config.height) as i32 - overall_return.ascent as i32,
-                            (overall_return.rbearing - overall_return.lbearing)
-                                .try_into()
-                                .unwrap(),
-                            (overall_return.ascent + overall_return.descent)
-                                .try_into()
-                                .unwrap(),
-                        );
-                    }
-                }
-            }
-        }
-    }
-    for (_, graphic) in graphics.iter() {
-        unsafe {
-            let set_color = |color: &graphics_data::Color| {
-                let color = x11::XColor::from_rgba(
-                    **display,
-                    x11::ffi::XDefaultScreen(**display),
-                    color.red,
-                    color.green,
-                    color.blue,
-                    255,
-                );
-                x11::ffi::XSetForeground(**display, gc, color.pixel)
-            };
-            match &graphic.as_ref().unwrap().drawable.as_ref().unwrap() {
-                Drawable::Rectangle {
-                    shape: _,
-                    x,
-                    y,
-                    w,
-                    h,
-                    fill,
-                    color,
-                } => {
-                    set_color(fill);
-                    x11::ffi::XFillRectangle(
-                        **display,
-                        window,
-                        gc,
-                        scale_x(*x, config.width) as i32,
-                        scale_y(*y, config.height) as i32,
-                        scale_w(*w, config.width) as u32,
-                        scale_h(*h, config.height) as u32,
-                    );
-                    set_color(color);
-                    x11::ffi::XDrawRectangle(
-                        **display,
-                        window,
-                        gc,
-                        scale_x(*x, config.width) as i32,
-                        scale_y(*y, config.height) as i32,
-                        scale_w(*w, config.width) as u32,
-                        scale_h(*h, config.height) as u32,
-                    );
-                }
-                Drawable::Vector {
-                    shape: _,
-                    color,
-                    vector,
-                } => {
-                    set_color(color);
-                    let points: Vec<_> = vector
-                        .iter()
-                        .map(|p| x11::XPoint {
-                            x: p.x as i16,
-                            y: p.y as i16,
-                        })
-                        .collect();
-                    x11::ffi::XDrawLines(
-                        **display,
-                        window,
-                        gc,
-                        points.as_ptr(),
-                        points.len() as i32,
-                        x11::coord_mode::CoordModeOrigin,
-                    );
-                }
-                Drawable::Text {
-                    text,
-                    size,
-                    color,
-                    x,
-                    y,
-                } => {
-                    set_color(color);
-                    match size {
-                        Size::Normal => {
-                            x11::ffi::XSetFont(**display, gc, (*config.body_font.unwrap()).fid)
-                        }
-                        Size::Large => {
-                            x11::ffi::XSetFont(**display, gc, (*config.title_font.unwrap()).fid)
-                        }
-                    };
-                    let s = CString::new(std::convert::AsRef::<str>::as_ref(text))?;
-                    let b = s.as_bytes();
-                    x11::ffi::XDrawString(
-                        **display,
-                        window,
-                        gc,
-                        scale_x(*x, config.width) as i32,
-                        scale_y(*y, config.height) as i32,
-                        b.as_ptr() as *const i8,
-                        b.len() as i32,
-                    );
-                }
-            }
-        }
-    }
-    unsafe {
-        x11::ffi::XFreeGC(**display, gc);
-        x11::ffi::XFlush(**display);
+/// Overrides `opt`'s X/Y/WIDTH/HEIGHT to cover whichever monitor `--monitor`/`--auto-monitor`
+/// selected, if either was given. Opens its own throwaway X connection to query RandR, since this
+/// needs to run before a backend claims its own.
+fn apply_monitor_geometry(opt: &mut Opt) -> eyre::Result<()> {
+    let selected = if let Some(name) = &opt.monitor {
+        let display = x11::Display::open()?;
+        monitor::get_monitors(&display)?
+            .into_iter()
+            .find(|m| &m.name == name)
+            .ok_or_else(|| eyre!("no connected monitor named {:?}", name))?
+    } else if opt.auto_monitor {
+        let display = x11::Display::open()?;
+        monitor::primary_monitor(&display)?.ok_or_else(|| eyre!("RandR has no primary monitor"))?
+    } else {
+        return Ok(());
     };
+    info!(?selected, "positioning overlay over monitor");
+    opt.x_position = selected.x;
+    opt.y_position = selected.y;
+    opt.width = selected.width;
+    opt.height = selected.height;
     Ok(())
 }
 
 #[instrument(skip(opt, rx))]
-async fn renderer(opt: Opt, mut rx: mpsc::Receiver<Command>) -> eyre::Result<()> {
+async fn renderer(mut opt: Opt, mut rx: mpsc::Receiver<Command>) -> eyre::Result<()> {
     info!("alive");
-    // open the display
-    let display;
-    let screen_number;
-    unsafe {
-        display = x11::XOpenDisplay(None).wrap_err("Failed to open display")?;
-        screen_number = x11::ffi::XDefaultScreen(*display);
-        let mut shape_event_base = MaybeUninit::uninit();
-        let mut shape_error_base = MaybeUninit::uninit();
-        if x11::ffi::XShapeQueryExtension(
-            *display,
-            shape_event_base.as_mut_ptr(),
-            shape_error_base.as_mut_ptr(),
-        ) == 0
-        {
-            bail!("Shape extension unavailable")
-        }
-        shape_event_base.assume_init();
-        shape_error_base.assume_init();
-    }
-
-    // create the window
-    debug!("creating window");
-    let window;
-    unsafe {
-        let background_color = x11::XColor::from_rgba(*display, screen_number, 0, 0, 0, 0);
-
-        let root = x11::ffi::XDefaultRootWindow(*display);
-
-        let mut visual_info = MaybeUninit::uninit();
-        x11::ffi::XMatchVisualInfo(
-            *display,
-            x11::ffi::XDefaultScreen(*display),
-            32,
-            x11::display_class::TrueColor,
-            visual_info.as_mut_ptr(),
-        );
-        let visual_info = visual_info.assume_init();
-        let colormap = x11::ffi::XCreateColormap(
-            *display,
-            x11::ffi::XDefaultRootWindow(*display),
-            visual_info.visual,
-            x11::create_colormap_alloc::AllocNone,
-        );
-
-        let mut attr = x11::XSetWindowAttributes {
-            background_pixmap: 0,
-            background_pixel: background_color.pixel,
-            border_pixel: 0,
-            win_gravity: x11::gravity::NorthWestGravity,
-            bit_gravity: x11::gravity::ForgetGravity,
-            save_under: 1,
-            event_mask: {
-                use x11::event_masks::*;
-                StructureNotifyMask
-                    | ExposureMask
-                    | PropertyChangeMask
-                    | EnterWindowMask
-                    | LeaveWindowMask
-                    | KeyPressMask
-                    | KeyReleaseMask
-                    | KeymapStateMask
-            },
-            do_not_propagate_mask: {
-                use x11::event_masks::*;
-                KeyPressMask
-                    | KeyReleaseMask
-                    | ButtonPressMask
-                    | ButtonReleaseMask
-                    | PointerMotionMask
-                    | ButtonMotionMask
-            },
-            override_redirect: 1,
-            colormap,
-            backing_pixel: 0,
-            backing_places: 0,
-            backing_store: 0,
-            border_pixmap: 0,
-            cursor: 0,
-        };
-
-        window = x11::ffi::XCreateWindow(
-            *display,
-            root,
-            opt.x_position,
-            opt.y_position,
-            opt.width,
-            opt.height,
-            0,
-            visual_info.depth,
-            x11::create_window_class::InputOutput,
-            visual_info.visual,
-            {
-                use x11::window_attributes::*;
-                CWColormap
-                    | CWBorderPixel
-                    | CWBackPixel
-                    | CWEventMask
-                    | CWWinGravity
-                    | CWBitGravity
-                    | CWSaveUnder
-                    | CWDontPropagate
-                    | CWOverrideRedirect
-            },
-            &mut attr,
-        );
-
-        x11::ffi::XShapeCombineMask(
-            *display,
-            window,
-            x11::shape_dest_kind::ShapeInput,
-            0,
-            0,
-            0,
-            x11::shape_op::ShapeSet,
-        );
-        x11::ffi::XShapeSelectInput(*display, window, x11::shape_notify::ShapeNotifyMask);
-
-        let region = x11::XFixesCreateRegion(*display, std::ptr::null_mut(), 0);
-        x11::XFixesSetWindowShapeRegion(
-            *display,
-            window,
-            x11::shape_dest_kind::ShapeInput,
-            0,
-            0,
-            region,
-        );
-        x11::XFixesDestroyRegion(*display, region);
 
-        x11::ffi::XMapWindow(*display, window);
+    if opt.headless && (opt.monitor.is_some() || opt.auto_monitor) {
+        return Err(eyre!(
+            "--monitor/--auto-monitor query RandR over a real X connection and aren't supported with --headless"
+        ));
     }
+    apply_monitor_geometry(&mut opt)?;
 
-    // allocate fonts
-    // TODO: do these just get leaked right now? whoops
-    let mut config = Config {
-        x_position: opt.x_position,
-        y_position: opt.y_position,
+    let geometry = Geometry {
         width: opt.width,
         height: opt.height,
-        title_font: None,
-        body_font: None,
     };
-    unsafe {
-        let s = CString::new("9x15bold")?;
-        let body_font = x11::ffi::XLoadQueryFont(*display, s.as_ptr());
-        if body_font.is_null() {
-            error!("fug");
-            return Err(eyre!("Failed to load font: 9x15bold"));
-        }
-        config.body_font = Some(body_font);
-        let s = CString::new("12x24")?;
-        let title_font = x11::ffi::XLoadQueryFont(*display, s.as_ptr());
-        if title_font.is_null() {
-            return Err(eyre!("Failed to load font: 12x24"));
-        }
-        config.title_font = Some(title_font);
+
+    if opt.headless && opt.track_window.is_some() {
+        return Err(eyre!("--track-window is only supported with --backend x11"));
     }
 
-    // draw something!
-    // debug!("drawing a square");
-    // unsafe {
-    //     let gc = x11::ffi::XCreateGC(*display, window, 0, std::ptr::null_mut());
-    //     x11::ffi::XSetForeground(*display, gc, red.pixel);
-    //     x11::ffi::XFillRectangle(*display, window, gc, 0, 0, 40, 40);
-    //     x11::ffi::XFreeGC(*display, gc);
-    //     x11::ffi::XFlush(*display);
-    // }
-
-    let mut graphics = HashMap::<(usize, String), Option<Graphic>>::new();
-    graphics.insert(
-        (0, "version-number".to_owned()),
-        Some(Graphic {
+    let mut renderer: Box<dyn Renderer> = if opt.headless {
+        Box::new(render::headless::HeadlessRenderer::new(
+            opt.width,
+            opt.height,
+            &opt.title_font,
+            &opt.body_font,
+        )?)
+    } else {
+        match opt.backend {
+            Backend::X11 => {
+                let display = x11::Display::open()?;
+                debug!("creating window");
+                let window = display.create_overlay_window(
+                    opt.x_position,
+                    opt.y_position,
+                    opt.width,
+                    opt.height,
+                )?;
+                let title_font = font::Font::load(&opt.title_font)?;
+                let body_font = font::Font::load(&opt.body_font)?;
+                let mut x11_renderer =
+                    X11Renderer::new(display, window, geometry, title_font, body_font);
+                if let Some(window) = opt.track_window {
+                    x11_renderer.track_window(window)?;
+                }
+                Box::new(x11_renderer)
+            }
+            Backend::Skia => {
+                if opt.track_window.is_some() {
+                    return Err(eyre!("--track-window is only supported with --backend x11"));
+                }
+                debug!("creating window");
+                Box::new(render::skia::SkiaRenderer::connect(
+                    opt.x_position,
+                    opt.y_position,
+                    opt.width,
+                    opt.height,
+                    &opt.title_font,
+                    &opt.body_font,
+                )?)
+            }
+        }
+    };
+
+    // Register the X connection (if this backend has one) with tokio so Expose/ConfigureNotify
+    // get handled as they arrive instead of only on the next redraw-triggering command.
+    let mut async_fd = renderer
+        .raw_fd()
+        .map(|fd| AsyncFd::new(BorrowedXConnFd(fd)))
+        .transpose()?;
+
+    let mut store = store::GraphicStore::new();
+    // The bounding box each live `(client_id, id)` last drew into, so a later frame only has to
+    // touch whatever changed since: union its old box (to erase) with its new one (to repaint).
+    let mut last_bbox = HashMap::<(usize, String), Rect>::new();
+    store.insert(
+        0,
+        Graphic {
             id: "test-rect".to_owned(),
             ttl: -1,
+            snapshot: None,
             drawable: Some(Drawable::Text {
                 x: 1175,
                 y: 975,
                 color: "#ffffff".try_into().unwrap(),
                 size: Size::Normal,
+                size_px: None,
                 text: "edmcoverlay CE".to_owned(),
             }),
-        }),
+        },
     );
     let mut interval = tokio::time::interval(std::time::Duration::from_secs(1) / FPS);
     debug!(sample_graphic = ?serde_json::to_string(&Graphic {
         id: "sample-graphic".to_owned(),
         ttl: 12345,
+        snapshot: None,
         drawable: Some(Drawable::Text {
             text: "".to_owned(),
             size: Size::Normal,
+            size_px: None,
             color: "#123456".try_into().unwrap(),
             x: 3,
             y: 14,
         }),
     }).unwrap());
     debug!("entering loop");
-    do_redraw(&config, &graphics, &[], &display, window)?;
+    for (key, graphic) in store.active() {
+        last_bbox.insert(key.clone(), renderer.bbox(graphic));
+    }
+    renderer.draw(
+        &snapshot_graphics(&store),
+        &[Rect {
+            x: 0,
+            y: 0,
+            width: geometry.width,
+            height: geometry.height,
+        }],
+    )?;
     loop {
         tokio::select! {
             Some(command) = rx.recv() => {
-                let mut command = command;
-                command.graphic.ttl *= isize::try_from(FPS)?;
-                let mut expired = Vec::new();
-                if let Some(Some(graphic)) = graphics.insert((command.client_id, command.graphic.id.to_owned()), Some(command.graphic)) {
-                    expired.push(graphic);
+                if let Some(path) = command.graphic.snapshot {
+                    debug!(?path, "snapshot requested");
+                    if path.ends_with(".svg") {
+                        // Rendered straight from the live graphic set rather than through a
+                        // `Renderer`, so this works on every backend, not just `--headless`.
+                        let live: Vec<Graphic> = store.active().map(|(_, g)| g.clone()).collect();
+                        let document = svg::render_svg(&live, geometry.width, geometry.height);
+                        std::fs::write(&path, document)
+                            .wrap_err_with(|| format!("failed to write svg snapshot {:?}", path))?;
+                    } else {
+                        renderer.snapshot(&path)?;
+                    }
+                    continue;
+                }
+                let key = (command.client_id, command.graphic.id.to_owned());
+                let mut dirty = Vec::new();
+                if let Some(old_bbox) = last_bbox.remove(&key) {
+                    dirty.push(old_bbox);
                 }
-                do_redraw(&config, &graphics, &expired, &display, window)?;
+                let new_bbox = command.graphic.drawable.as_ref().map(|_| renderer.bbox(&command.graphic));
+                store.insert(command.client_id, command.graphic);
+                if let Some(new_bbox) = new_bbox {
+                    last_bbox.insert(key, new_bbox);
+                    dirty.push(new_bbox);
+                }
+                renderer.draw(&snapshot_graphics(&store), &dirty)?;
             },
             _ = interval.tick() => {
-                let mut expired = Vec::new();
-                for (_, graphic) in graphics.iter_mut() {
-                    if let Some(Graphic { ref mut ttl, ref id, .. }) = graphic {
-                        if *ttl == 0 {
-                            debug!(graphic_id = ?id, "ttl expired");
-                            expired.push(graphic.take().unwrap());
-                            continue;
-                        }
-                        if *ttl > 0 {
-                            *ttl -= 1;
-                        }
-                    }
-                }
-                graphics.retain(|_, v| v.is_some());
-                do_redraw(&config, &graphics, &expired, &display, window)?;
+                let expired_keys = store.reap(std::time::Instant::now());
+                let dirty: Vec<Rect> = expired_keys
+                    .into_iter()
+                    .filter_map(|key| last_bbox.remove(&key))
+                    .collect();
+                renderer.draw(&snapshot_graphics(&store), &dirty)?;
+            },
+            Ok(mut guard) = async_fd.as_mut().unwrap().readable(), if async_fd.is_some() => {
+                renderer.handle_events(&snapshot_graphics(&store))?;
+                guard.clear_ready();
             },
         }
     }
@@ -538,10 +354,13 @@ async fn listener(tx: mpsc::Sender<Command>) -> eyre::Result<()> {
                         .wrap_err_with(|| eyre!("could not parse line {:?}", line))
                     {
                         Ok(graphic) => {
-                            if graphic.drawable.is_none() {
+                            let forwardable = graphic.drawable.is_some()
+                                || graphic.snapshot.is_some()
+                                || graphic.ttl == 0;
+                            if !forwardable {
                                 warn!(?line, ?graphic, "invalid drawable");
                             }
-                            if graphic.drawable.is_some() || graphic.ttl == 0 {
+                            if forwardable {
                                 tx.send(Command { client_id, graphic }).await?;
                             }
                         }
@@ -577,7 +396,36 @@ async fn main() -> Result<(), eyre::Report> {
     let (tx, rx): (mpsc::Sender<Command>, _) = mpsc::channel(100);
 
     let renderer = tokio::spawn(renderer(opt.clone(), rx));
-    let listener = tokio::spawn(listener(tx));
+
+    // Route the listener's outgoing commands through a capturing relay when asked, so a
+    // reported glitch can be replayed later without the game running.
+    let listener_tx = if let Some(capture_path) = opt.capture.clone() {
+        let (capture_tx, mut capture_rx) = mpsc::channel::<Command>(100);
+        let forward_tx = tx.clone();
+        tokio::spawn(async move {
+            let mut writer = capture::CaptureWriter::create(&capture_path)?;
+            while let Some(command) = capture_rx.recv().await {
+                writer.record(&capture::CapturedMessage::Graphic(command.graphic.clone()))?;
+                forward_tx
+                    .send(command)
+                    .await
+                    .wrap_err("renderer task is gone")?;
+            }
+            Ok::<(), eyre::Report>(())
+        });
+        capture_tx
+    } else {
+        tx.clone()
+    };
+    let listener = tokio::spawn(listener(listener_tx));
+
+    if let Some(replay_path) = opt.replay.clone() {
+        let replay_tx = tx.clone();
+        let fast = opt.replay_fast;
+        tokio::spawn(async move {
+            capture::replay(&replay_path, REPLAY_CLIENT_ID, fast, &replay_tx).await
+        });
+    }
 
     tokio::select! {
         result = renderer => result??,
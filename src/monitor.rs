@@ -0,0 +1,72 @@
+//! Multi-monitor enumeration via the RandR extension, so the overlay can be positioned over
+//! whichever physical output is running the game instead of always landing on the primary one.
+
+use eyre::WrapErr;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as _;
+
+use crate::x11::Display;
+
+/// A physical output's geometry in root-window coordinates.
+#[derive(Clone, Debug)]
+pub struct Monitor {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+}
+
+/// Every monitor RandR currently reports a CRTC for, i.e. every output that's connected and
+/// actively displaying something. Disconnected or disabled outputs are skipped since they have
+/// no geometry to report.
+#[tracing::instrument(skip(display))]
+pub fn get_monitors<C: Connection>(display: &Display<C>) -> eyre::Result<Vec<Monitor>> {
+    let root = display.root();
+    let primary = display
+        .conn
+        .randr_get_output_primary(root)?
+        .reply()
+        .wrap_err("RRGetOutputPrimary failed")?
+        .output;
+    let resources = display
+        .conn
+        .randr_get_screen_resources_current(root)?
+        .reply()
+        .wrap_err("RRGetScreenResourcesCurrent failed")?;
+
+    let mut monitors = Vec::new();
+    for output in resources.outputs {
+        let output_info = display
+            .conn
+            .randr_get_output_info(output, resources.config_timestamp)?
+            .reply()
+            .wrap_err("RRGetOutputInfo failed")?;
+        if output_info.crtc == 0 {
+            continue;
+        }
+        let crtc_info = display
+            .conn
+            .randr_get_crtc_info(output_info.crtc, resources.config_timestamp)?
+            .reply()
+            .wrap_err("RRGetCrtcInfo failed")?;
+        if crtc_info.width == 0 || crtc_info.height == 0 {
+            continue;
+        }
+        monitors.push(Monitor {
+            name: String::from_utf8_lossy(&output_info.name).into_owned(),
+            x: i32::from(crtc_info.x),
+            y: i32::from(crtc_info.y),
+            width: u32::from(crtc_info.width),
+            height: u32::from(crtc_info.height),
+            primary: output == primary,
+        });
+    }
+    Ok(monitors)
+}
+
+/// The monitor RandR considers primary, if one is set and still connected.
+pub fn primary_monitor<C: Connection>(display: &Display<C>) -> eyre::Result<Option<Monitor>> {
+    Ok(get_monitors(display)?.into_iter().find(|m| m.primary))
+}
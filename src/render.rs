@@ -0,0 +1,589 @@
+//! Backends that turn the current set of [`Graphic`]s into pixels on screen.
+//!
+//! Historically there was only one way to draw: the core-X11 `do_redraw` path, which repainted
+//! every graphic every frame and erased expired ones by overpainting their bounding box in black
+//! — visible as flicker, and the black overpaint could clip a neighbouring graphic it happened to
+//! overlap. Redraws are now damage-tracked: the caller works out which screen regions changed
+//! since the last frame (see `last_bbox` in `main.rs`) and passes them as `dirty`; a backend only
+//! has to erase and repaint whatever intersects that region.
+
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
+
+use tracing::{event, instrument, Level};
+use x11rb::connection::Connection as _;
+use x11rb::protocol::xproto::ConnectionExt as _;
+use x11rb::protocol::Event;
+
+use crate::font::Font;
+use crate::graphics_data::{Drawable, Graphic, Size};
+use crate::x11;
+
+pub mod headless;
+pub mod skia;
+
+pub(crate) fn scale_w(x: usize, width: u32) -> usize {
+    x * width as usize / 1280
+}
+pub(crate) fn scale_h(y: usize, height: u32) -> usize {
+    y * height as usize / 1024
+}
+pub(crate) fn scale_x(x: usize, width: u32) -> usize {
+    scale_w(x, width) + 20
+}
+pub(crate) fn scale_y(y: usize, height: u32) -> usize {
+    scale_h(y, height) + 40
+}
+
+/// The window geometry graphics are scaled against; kept separate from whichever backend owns
+/// the actual surface so both backends agree on where things go.
+#[derive(Debug, Clone, Copy)]
+pub struct Geometry {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// An axis-aligned region of the window, in the same pixel space as [`Geometry`]. Used to track
+/// damage: the area a graphic currently occupies, and the union of areas that changed since the
+/// last frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// The smallest `Rect` containing both `self` and `other`.
+    pub fn union(self, other: Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width as i32).max(other.x + other.width as i32);
+        let bottom = (self.y + self.height as i32).max(other.y + other.height as i32);
+        Rect {
+            x,
+            y,
+            width: (right - x) as u32,
+            height: (bottom - y) as u32,
+        }
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.x + other.width as i32
+            && other.x < self.x + self.width as i32
+            && self.y < other.y + other.height as i32
+            && other.y < self.y + self.height as i32
+    }
+}
+
+/// Pads `rect` by `stroke_radius` pixels on every side, with a floor of 1px even when
+/// `stroke_radius` is 0.
+///
+/// A perfectly horizontal or vertical `Vector`/`Line`, or a `Circle`/`Ellipse` small enough that
+/// its scaled size rounds down to 0, otherwise collapses to a zero-width or zero-height bounding
+/// box. That's fatal two ways over: X11's `SetClipRectangles` treats a zero-area rectangle as an
+/// empty clip region, suppressing every draw call restricted to it, and `Rect::intersects` uses
+/// strict `<` comparisons under which a zero-area rect never intersects anything (including a
+/// copy of itself) — so even backends that don't clip skip painting it as "not dirty". The
+/// padding also needs to cover a `Line`'s stroke width, since a thick line extends past the bare
+/// endpoint-to-endpoint box.
+fn pad_clip_rect(rect: Rect, stroke_radius: u32) -> Rect {
+    let pad = stroke_radius.max(1) as i32;
+    Rect {
+        x: rect.x - pad,
+        y: rect.y - pad,
+        width: (rect.width as i32 + pad * 2) as u32,
+        height: (rect.height as i32 + pad * 2) as u32,
+    }
+}
+
+/// The bounding box `drawable` occupies at the given window `width`/`height`, in the same
+/// scaled pixel space [`scale_x`]/[`scale_y`] place it in. Shared by every backend so damage
+/// tracking agrees with wherever a graphic is actually drawn; `title_font`/`body_font` are needed
+/// because a `Text` drawable's extent depends on how the string shapes.
+pub(crate) fn drawable_bbox(
+    drawable: &Drawable,
+    width: u32,
+    height: u32,
+    title_font: &Font,
+    body_font: &Font,
+) -> Rect {
+    match drawable {
+        Drawable::Rectangle { x, y, w, h, .. } => Rect {
+            x: scale_x(*x, width) as i32,
+            y: scale_y(*y, height) as i32,
+            width: scale_w(*w, width) as u32,
+            height: scale_h(*h, height) as u32,
+        },
+        Drawable::Vector { vector, .. } => {
+            let (xmin, xmax, ymin, ymax) = vector.iter().fold((0, 0, 0, 0), |acc, val| {
+                (
+                    val.x.min(acc.0),
+                    val.x.max(acc.1),
+                    val.y.min(acc.2),
+                    val.y.max(acc.3),
+                )
+            });
+            let rect = Rect {
+                x: scale_x(xmin, width) as i32,
+                y: scale_y(ymin, height) as i32,
+                width: scale_w(xmax - xmin, width) as u32,
+                height: scale_h(ymax - ymin, height) as u32,
+            };
+            pad_clip_rect(rect, 0)
+        }
+        Drawable::Circle { x, y, radius, .. } => {
+            let left = x.saturating_sub(*radius);
+            let top = y.saturating_sub(*radius);
+            let rect = Rect {
+                x: scale_x(left, width) as i32,
+                y: scale_y(top, height) as i32,
+                width: scale_w(radius * 2, width) as u32,
+                height: scale_h(radius * 2, height) as u32,
+            };
+            pad_clip_rect(rect, 0)
+        }
+        Drawable::Ellipse { x, y, rx, ry, .. } => {
+            let left = x.saturating_sub(*rx);
+            let top = y.saturating_sub(*ry);
+            let rect = Rect {
+                x: scale_x(left, width) as i32,
+                y: scale_y(top, height) as i32,
+                width: scale_w(rx * 2, width) as u32,
+                height: scale_h(ry * 2, height) as u32,
+            };
+            pad_clip_rect(rect, 0)
+        }
+        Drawable::Line {
+            x1,
+            y1,
+            x2,
+            y2,
+            width: line_width,
+            ..
+        } => {
+            let xmin = (*x1).min(*x2);
+            let xmax = (*x1).max(*x2);
+            let ymin = (*y1).min(*y2);
+            let ymax = (*y1).max(*y2);
+            let rect = Rect {
+                x: scale_x(xmin, width) as i32,
+                y: scale_y(ymin, height) as i32,
+                width: scale_w(xmax - xmin, width) as u32,
+                height: scale_h(ymax - ymin, height) as u32,
+            };
+            // Backends stroke at the unscaled `width`, so pad by that rather than a scaled one.
+            let stroke_radius = (*line_width as u32 + 1) / 2;
+            pad_clip_rect(rect, stroke_radius)
+        }
+        Drawable::Text {
+            text,
+            size,
+            size_px,
+            x,
+            y,
+            ..
+        } => {
+            let font = match size {
+                Size::Normal => body_font,
+                Size::Large => title_font,
+            };
+            let size_px = size_px.unwrap_or_else(|| size.default_px());
+            let text_width: f32 = font.shape(text, size_px).iter().map(|g| g.x_advance).sum();
+            let text_height = font.ascender_px(size_px) + font.descender_px(size_px);
+            Rect {
+                x: scale_x(*x, width) as i32,
+                y: scale_y(*y, height) as i32 - font.ascender_px(size_px).round() as i32,
+                width: text_width.ceil() as u32,
+                height: text_height.ceil() as u32,
+            }
+        }
+    }
+}
+
+/// A surface that can turn a frame's worth of [`Graphic`]s into pixels.
+///
+/// `graphics` is the complete live set; `dirty` is the union of bounding boxes that changed since
+/// the last call (a graphic was added, moved, or expired) and is the only region a backend needs
+/// to erase and repaint. An empty `dirty` means nothing changed and `draw` can be a no-op.
+pub trait Renderer {
+    fn draw(
+        &mut self,
+        graphics: &HashMap<(usize, String), Option<Graphic>>,
+        dirty: &[Rect],
+    ) -> eyre::Result<()>;
+
+    /// The bounding box `graphic` currently occupies, for the caller to fold into next frame's
+    /// `dirty` list. Not a free function because text bboxes depend on whichever font file this
+    /// backend loaded for its `size`.
+    fn bbox(&self, graphic: &Graphic) -> Rect;
+
+    /// Writes the current frame out as a PNG at `path`. Only the offscreen
+    /// [`headless::HeadlessRenderer`] backend supports this; the windowed backends draw straight
+    /// to the screen and have no frame buffer worth snapshotting.
+    fn snapshot(&mut self, path: &str) -> eyre::Result<()> {
+        let _ = path;
+        Err(eyre::eyre!(
+            "this backend doesn't support snapshotting; pass --headless"
+        ))
+    }
+
+    /// The X connection's file descriptor, for the caller to register with its own event loop
+    /// (we use tokio's `AsyncFd`) and call [`Renderer::handle_events`] once it's readable. `None`
+    /// for backends, like the offscreen one, with no X connection to watch.
+    fn raw_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// Drains and handles whatever X events are currently queued: `Expose` triggers a redraw
+    /// (of the exposed area, or of the whole window on backends that always repaint it all), and `ConfigureNotify` updates the tracked window geometry so `scale_*` stays
+    /// correct after the overlay window is resized. Only called after `raw_fd` returned `Some`
+    /// and that fd became readable.
+    fn handle_events(
+        &mut self,
+        graphics: &HashMap<(usize, String), Option<Graphic>>,
+    ) -> eyre::Result<()> {
+        let _ = graphics;
+        Ok(())
+    }
+}
+
+/// The original core-X11 backend: draws directly into the override-redirect window with
+/// core X11 requests, erasing the dirty region to transparent and repainting whatever intersects it.
+pub struct X11Renderer {
+    pub display: x11::Display<x11rb::rust_connection::RustConnection>,
+    pub window: x11::Window,
+    pub geometry: Geometry,
+    pub title_font: Font,
+    pub body_font: Font,
+    /// The other window (usually the game's) this overlay is following, if any; see
+    /// [`X11Renderer::track_window`].
+    tracked_window: Option<x11::Window>,
+}
+
+impl X11Renderer {
+    pub fn new(
+        display: x11::Display<x11rb::rust_connection::RustConnection>,
+        window: x11::Window,
+        geometry: Geometry,
+        title_font: Font,
+        body_font: Font,
+    ) -> Self {
+        X11Renderer {
+            display,
+            window,
+            geometry,
+            title_font,
+            body_font,
+            tracked_window: None,
+        }
+    }
+
+    /// Starts following `window`'s position and size: once subscribed, a `ConfigureNotify` on it
+    /// (seen in [`Renderer::handle_events`]) moves and resizes the overlay to match, keeping it
+    /// glued to the game window as it's dragged or resized.
+    pub fn track_window(&mut self, window: x11::Window) -> eyre::Result<()> {
+        x11::track_window(&self.display, window)?;
+        self.tracked_window = Some(window);
+        Ok(())
+    }
+
+    fn font_for(&self, size: Size) -> &Font {
+        match size {
+            Size::Normal => &self.body_font,
+            Size::Large => &self.title_font,
+        }
+    }
+
+    /// Shapes and rasterizes `text` at `size_px`, blitting each glyph in `color` (its coverage
+    /// scaled into the alpha byte) starting at pen position `(x, y)` (the text baseline, matching the old
+    /// `ImageText8` convention).
+    fn draw_text(
+        &self,
+        window: x11::Window,
+        gc: x11::Gcontext,
+        font: &Font,
+        text: &str,
+        size_px: f32,
+        x: i32,
+        y: i32,
+        color: &crate::graphics_data::Color,
+    ) -> eyre::Result<()> {
+        let mut pen_x = x as f32;
+        let mut pen_y = y as f32;
+        for glyph in font.shape(text, size_px) {
+            if let Some(bitmap) = font.rasterize(glyph.glyph_id, size_px) {
+                let pixels: Vec<u32> = bitmap
+                    .coverage
+                    .iter()
+                    .map(|&coverage| {
+                        let alpha = u32::from(coverage) * u32::from(color.alpha) / 255;
+                        (alpha << 24)
+                            | (u32::from(color.red) << 16)
+                            | (u32::from(color.green) << 8)
+                            | u32::from(color.blue)
+                    })
+                    .collect();
+                x11::put_image_rgba(
+                    &self.display,
+                    window,
+                    gc,
+                    (pen_x + glyph.x_offset) as i32 + bitmap.x_offset,
+                    (pen_y + glyph.y_offset) as i32 + bitmap.y_offset,
+                    bitmap.width,
+                    bitmap.height,
+                    &pixels,
+                )?;
+            }
+            pen_x += glyph.x_advance;
+            pen_y += glyph.y_advance;
+        }
+        Ok(())
+    }
+}
+
+impl Renderer for X11Renderer {
+    // TODO: enable once https://github.com/tokio-rs/tracing/issues/1318 is fixed
+    #[instrument(skip(self, graphics))]
+    fn draw(
+        &mut self,
+        graphics: &HashMap<(usize, String), Option<Graphic>>,
+        dirty: &[Rect],
+    ) -> eyre::Result<()> {
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        event!(
+            Level::TRACE,
+            ?graphics,
+            ?dirty,
+            "redrawing {} graphics within {} dirty rect(s)",
+            graphics.len(),
+            dirty.len()
+        );
+        let display = &self.display;
+        let window = self.window;
+        let width = self.geometry.width;
+        let height = self.geometry.height;
+
+        let gc = x11::create_gc(display, window)?;
+        x11::set_clip_rectangles(display, gc, dirty)?;
+
+        // Erase the whole dirty region to transparent before repainting whatever still
+        // intersects it, rather than overpainting only the exact old boxes of expired graphics
+        // (which could clip a neighbour that happens to overlap one).
+        x11::set_foreground(display, gc, display.alloc_rgba(0, 0, 0, 0)?)?;
+        for rect in dirty {
+            x11::fill_rectangle(display, window, gc, rect.x, rect.y, rect.width, rect.height)?;
+        }
+
+        for (_, graphic) in graphics.iter() {
+            let graphic = match graphic.as_ref() {
+                Some(graphic) => graphic,
+                None => continue,
+            };
+            let drawable = match graphic.drawable.as_ref() {
+                Some(drawable) => drawable,
+                // `GraphicStore` removes an id on a delete-on-arrival command (no drawable) rather
+                // than keeping it, so this only skips entries from some other source.
+                None => continue,
+            };
+            let bbox = drawable_bbox(drawable, width, height, &self.title_font, &self.body_font);
+            if !dirty.iter().any(|d| d.intersects(&bbox)) {
+                continue;
+            }
+            let set_color = |color: &crate::graphics_data::Color| -> eyre::Result<()> {
+                let pixel = display.alloc_rgba(color.red, color.green, color.blue, color.alpha)?;
+                x11::set_foreground(display, gc, pixel)
+            };
+            match drawable {
+                Drawable::Rectangle {
+                    shape: _,
+                    x,
+                    y,
+                    w,
+                    h,
+                    fill,
+                    color,
+                } => {
+                    set_color(fill)?;
+                    x11::fill_rectangle(
+                        display,
+                        window,
+                        gc,
+                        scale_x(*x, width) as i32,
+                        scale_y(*y, height) as i32,
+                        scale_w(*w, width) as u32,
+                        scale_h(*h, height) as u32,
+                    )?;
+                    set_color(color)?;
+                    x11::draw_rectangle(
+                        display,
+                        window,
+                        gc,
+                        scale_x(*x, width) as i32,
+                        scale_y(*y, height) as i32,
+                        scale_w(*w, width) as u32,
+                        scale_h(*h, height) as u32,
+                    )?;
+                }
+                Drawable::Vector {
+                    shape: _,
+                    color,
+                    vector,
+                } => {
+                    set_color(color)?;
+                    let points: Vec<_> = vector
+                        .iter()
+                        .map(|p| x11::Point {
+                            x: scale_x(p.x, width) as i16,
+                            y: scale_y(p.y, height) as i16,
+                        })
+                        .collect();
+                    x11::draw_lines(display, window, gc, &points)?;
+                }
+                Drawable::Circle {
+                    shape: _,
+                    x,
+                    y,
+                    radius,
+                    fill,
+                    color,
+                } => {
+                    let left = x.saturating_sub(*radius);
+                    let top = y.saturating_sub(*radius);
+                    let px = scale_x(left, width) as i32;
+                    let py = scale_y(top, height) as i32;
+                    let w = scale_w(radius * 2, width) as u32;
+                    let h = scale_h(radius * 2, height) as u32;
+                    set_color(fill)?;
+                    x11::fill_arc(display, window, gc, px, py, w, h)?;
+                    set_color(color)?;
+                    x11::draw_arc(display, window, gc, px, py, w, h)?;
+                }
+                Drawable::Ellipse {
+                    shape: _,
+                    x,
+                    y,
+                    rx,
+                    ry,
+                    fill,
+                    color,
+                } => {
+                    let left = x.saturating_sub(*rx);
+                    let top = y.saturating_sub(*ry);
+                    let px = scale_x(left, width) as i32;
+                    let py = scale_y(top, height) as i32;
+                    let w = scale_w(rx * 2, width) as u32;
+                    let h = scale_h(ry * 2, height) as u32;
+                    set_color(fill)?;
+                    x11::fill_arc(display, window, gc, px, py, w, h)?;
+                    set_color(color)?;
+                    x11::draw_arc(display, window, gc, px, py, w, h)?;
+                }
+                Drawable::Line {
+                    shape: _,
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    width: line_width,
+                    color,
+                } => {
+                    set_color(color)?;
+                    x11::set_line_width(display, gc, *line_width as u32)?;
+                    let points = [
+                        x11::Point {
+                            x: scale_x(*x1, width) as i16,
+                            y: scale_y(*y1, height) as i16,
+                        },
+                        x11::Point {
+                            x: scale_x(*x2, width) as i16,
+                            y: scale_y(*y2, height) as i16,
+                        },
+                    ];
+                    x11::draw_lines(display, window, gc, &points)?;
+                    // Reset so this doesn't leak into other graphics sharing this frame's GC.
+                    x11::set_line_width(display, gc, 0)?;
+                }
+                Drawable::Text {
+                    text,
+                    size,
+                    size_px,
+                    color,
+                    x,
+                    y,
+                } => {
+                    let font = self.font_for(*size);
+                    let size_px = size_px.unwrap_or_else(|| size.default_px());
+                    self.draw_text(
+                        window,
+                        gc,
+                        font,
+                        text,
+                        size_px,
+                        scale_x(*x, width) as i32,
+                        scale_y(*y, height) as i32,
+                        color,
+                    )?;
+                }
+            }
+        }
+        display.conn.free_gc(gc)?.check()?;
+        display.conn.flush()?;
+        Ok(())
+    }
+
+    fn bbox(&self, graphic: &Graphic) -> Rect {
+        drawable_bbox(
+            graphic.drawable.as_ref().unwrap(),
+            self.geometry.width,
+            self.geometry.height,
+            &self.title_font,
+            &self.body_font,
+        )
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        use std::os::unix::io::AsRawFd;
+        Some(self.display.conn.as_raw_fd())
+    }
+
+    fn handle_events(
+        &mut self,
+        graphics: &HashMap<(usize, String), Option<Graphic>>,
+    ) -> eyre::Result<()> {
+        while let Some(event) = x11::poll_event(&self.display)? {
+            match event {
+                Event::Expose(ev) => {
+                    let rect = Rect {
+                        x: i32::from(ev.x),
+                        y: i32::from(ev.y),
+                        width: u32::from(ev.width),
+                        height: u32::from(ev.height),
+                    };
+                    self.draw(graphics, &[rect])?;
+                }
+                Event::ConfigureNotify(ev) if ev.window == self.window => {
+                    self.geometry = Geometry {
+                        width: u32::from(ev.width),
+                        height: u32::from(ev.height),
+                    };
+                }
+                Event::ConfigureNotify(ev) if Some(ev.window) == self.tracked_window => {
+                    x11::configure_window(
+                        &self.display,
+                        self.window,
+                        i32::from(ev.x),
+                        i32::from(ev.y),
+                        u32::from(ev.width),
+                        u32::from(ev.height),
+                    )?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
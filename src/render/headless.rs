@@ -0,0 +1,156 @@
+//! An offscreen backend for testing and previewing: draws into an in-memory raster surface
+//! instead of mapping a real window, and can dump the current frame out as a PNG on request.
+//!
+//! Shares its drawing logic with [`super::skia::SkiaRenderer`] (same [`skia_safe::Canvas`] calls
+//! via [`super::skia::draw_graphics`]) but uses a CPU raster `Surface` instead of an EGL-backed
+//! one, so it needs neither an X server nor a GPU — this is what makes golden-image tests and
+//! previewing overlays without Elite Dangerous running possible.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use eyre::WrapErr;
+use skia_safe::{AlphaType, ColorType, ImageInfo, Rect as SkRect, Surface};
+
+use crate::font::Font;
+use crate::graphics_data::Graphic;
+use crate::render::skia::{draw_graphics, load_font_pair};
+use crate::render::{drawable_bbox, Geometry, Rect, Renderer};
+
+pub struct HeadlessRenderer {
+    surface: Surface,
+    geometry: Geometry,
+    title_font: Font,
+    body_font: Font,
+    title_typeface: skia_safe::Typeface,
+    body_typeface: skia_safe::Typeface,
+}
+
+impl HeadlessRenderer {
+    #[tracing::instrument]
+    pub fn new(
+        width: u32,
+        height: u32,
+        title_font_path: &Path,
+        body_font_path: &Path,
+    ) -> eyre::Result<HeadlessRenderer> {
+        let surface = Surface::new_raster_n32_premul((width as i32, height as i32))
+            .ok_or_else(|| eyre::eyre!("failed to allocate an offscreen raster surface"))?;
+        let (title_font, title_typeface) = load_font_pair(title_font_path)?;
+        let (body_font, body_typeface) = load_font_pair(body_font_path)?;
+        Ok(HeadlessRenderer {
+            surface,
+            geometry: Geometry { width, height },
+            title_font,
+            body_font,
+            title_typeface,
+            body_typeface,
+        })
+    }
+}
+
+impl Renderer for HeadlessRenderer {
+    fn draw(
+        &mut self,
+        graphics: &HashMap<(usize, String), Option<Graphic>>,
+        dirty: &[Rect],
+    ) -> eyre::Result<()> {
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        let width = self.geometry.width;
+        let height = self.geometry.height;
+        let canvas = self.surface.canvas();
+        // Unlike the GPU backend, this surface persists across frames instead of being swapped,
+        // so only the dirty region needs erasing before the (likewise dirty-filtered) redraw.
+        for rect in dirty {
+            canvas.save();
+            canvas.clip_rect(
+                SkRect::from_xywh(
+                    rect.x as f32,
+                    rect.y as f32,
+                    rect.width as f32,
+                    rect.height as f32,
+                ),
+                None,
+                true,
+            );
+            canvas.clear(skia_safe::Color::TRANSPARENT);
+            canvas.restore();
+        }
+        draw_graphics(
+            canvas,
+            graphics,
+            dirty,
+            width,
+            height,
+            &self.title_font,
+            &self.title_typeface,
+            &self.body_font,
+            &self.body_typeface,
+        );
+        Ok(())
+    }
+
+    fn bbox(&self, graphic: &Graphic) -> Rect {
+        drawable_bbox(
+            graphic.drawable.as_ref().unwrap(),
+            self.geometry.width,
+            self.geometry.height,
+            &self.title_font,
+            &self.body_font,
+        )
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn snapshot(&mut self, path: &str) -> eyre::Result<()> {
+        let (width, height, pixels) = self.read_rgba()?;
+        let buffer = image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| eyre::eyre!("pixel buffer didn't match the surface dimensions"))?;
+        buffer
+            .save(path)
+            .wrap_err_with(|| format!("failed to write snapshot PNG to {:?}", path))
+    }
+}
+
+impl HeadlessRenderer {
+    /// Reads the current frame back as 8-bit RGBA rows, top-to-bottom, for whichever of
+    /// [`HeadlessRenderer::read_pixels`] or [`Renderer::snapshot`] the caller wants.
+    fn read_rgba(&mut self) -> eyre::Result<(u32, u32, Vec<u8>)> {
+        let width = self.geometry.width;
+        let height = self.geometry.height;
+        let info = ImageInfo::new(
+            (width as i32, height as i32),
+            ColorType::RGBA8888,
+            AlphaType::Unpremul,
+            None,
+        );
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+        let read = self
+            .surface
+            .read_pixels(&info, &mut pixels, (width * 4) as usize, (0, 0));
+        if !read {
+            return Err(eyre::eyre!(
+                "failed to read back the offscreen surface's pixels"
+            ));
+        }
+        Ok((width, height, pixels))
+    }
+
+    /// Reads the current frame back as packed `0xAARRGGBB` pixels — the same convention
+    /// [`crate::x11::put_image_rgba`] blits and [`crate::x11::Display::alloc_rgba`] packs — so downstream
+    /// tooling (golden-image tests, a preview UI) can assert on or display the rendered overlay
+    /// without going through a PNG file on disk.
+    pub fn read_pixels(&mut self) -> eyre::Result<Vec<u32>> {
+        let (_, _, rgba) = self.read_rgba()?;
+        Ok(rgba
+            .chunks_exact(4)
+            .map(|p| {
+                (u32::from(p[3]) << 24)
+                    | (u32::from(p[0]) << 16)
+                    | (u32::from(p[1]) << 8)
+                    | u32::from(p[2])
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,485 @@
+//! A GPU backend that draws through [`skia_safe`] into an EGL context on the override-redirect
+//! window, giving antialiased rectangles, smooth vector paths and real text shaping instead of
+//! the aliased core-X11 primitives.
+//!
+//! Unlike [`super::X11Renderer`], which erases and repaints only the dirty region, this backend
+//! clears and repaints the whole surface on every frame with any damage — much simpler, and the
+//! GPU makes full repaints cheap.
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path as FsPath;
+
+use eyre::WrapErr;
+use khronos_egl as egl;
+use skia_safe::gpu::gl::FramebufferInfo;
+use skia_safe::gpu::{BackendRenderTarget, DirectContext, SurfaceOrigin};
+use skia_safe::{
+    Canvas, Color4f, Font as SkFont, Paint, PaintStyle, Path, Point as SkPoint, Rect, Surface,
+    TextBlobBuilder, Typeface,
+};
+use tracing::instrument;
+use x11rb::protocol::Event;
+use x11rb::xcb_ffi::XCBConnection;
+
+use crate::font::Font;
+use crate::graphics_data::{Drawable, Graphic, Size};
+use crate::render::{
+    drawable_bbox, scale_h, scale_w, scale_x, scale_y, Geometry, Rect as DamageRect, Renderer,
+};
+use crate::x11;
+
+/// The GPU backend's own X connection and EGL context.
+///
+/// This deliberately doesn't share the [`x11::Display<RustConnection>`] the X11 backend uses:
+/// EGL needs a raw XCB connection handle to create a platform display, which the pure-Rust
+/// [`x11rb::rust_connection::RustConnection`] doesn't expose, so the Skia backend opens its own
+/// [`XCBConnection`] and window instead.
+///
+/// [`x11::Display<RustConnection>`]: crate::x11::Display
+pub struct SkiaRenderer {
+    // Order matters: `surface`/`gr_context` borrow from the EGL context, which must outlive them.
+    surface: Surface,
+    gr_context: DirectContext,
+    egl: egl::Instance<egl::Static>,
+    egl_display: egl::Display,
+    egl_context: egl::Context,
+    egl_surface: egl::Surface,
+    display: x11::Display<XCBConnection>,
+    geometry: Geometry,
+    title_font: Font,
+    body_font: Font,
+    title_typeface: Typeface,
+    body_typeface: Typeface,
+}
+
+impl SkiaRenderer {
+    /// Opens its own XCB connection, creates the overlay window through it, and stands up an EGL
+    /// context on top.
+    #[instrument]
+    pub fn connect(
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        title_font_path: &FsPath,
+        body_font_path: &FsPath,
+    ) -> eyre::Result<SkiaRenderer> {
+        let (conn, screen) = XCBConnection::connect(None).wrap_err("Failed to open display")?;
+        let display = x11::Display::from_connection(conn, screen)?;
+        let window = display.create_overlay_window(x, y, width, height)?;
+        Self::new(
+            display,
+            window,
+            Geometry { width, height },
+            title_font_path,
+            body_font_path,
+        )
+    }
+
+    fn new(
+        display: x11::Display<XCBConnection>,
+        window: x11::Window,
+        geometry: Geometry,
+        title_font_path: &FsPath,
+        body_font_path: &FsPath,
+    ) -> eyre::Result<SkiaRenderer> {
+        let (title_font, title_typeface) = load_font_pair(title_font_path)?;
+        let (body_font, body_typeface) = load_font_pair(body_font_path)?;
+
+        let egl = egl::Instance::new(egl::Static);
+        let egl_display = unsafe {
+            egl.get_platform_display(
+                egl::PLATFORM_XCB_EXT,
+                display.conn.get_raw_xcb_connection() as *mut c_void,
+                &[egl::ATTRIB_NONE],
+            )
+        }
+        .wrap_err("eglGetPlatformDisplay failed")?;
+        egl.initialize(egl_display)
+            .wrap_err("eglInitialize failed")?;
+
+        let config_attribs = [
+            egl::RED_SIZE,
+            8,
+            egl::GREEN_SIZE,
+            8,
+            egl::BLUE_SIZE,
+            8,
+            egl::ALPHA_SIZE,
+            8,
+            egl::SURFACE_TYPE,
+            egl::WINDOW_BIT,
+            egl::RENDERABLE_TYPE,
+            egl::OPENGL_BIT,
+            egl::NONE,
+        ];
+        let config = egl
+            .choose_first_config(egl_display, &config_attribs)
+            .wrap_err("eglChooseConfig failed")?
+            .ok_or_else(|| eyre::eyre!("no suitable EGL config for a 32-bit ARGB visual"))?;
+
+        egl.bind_api(egl::OPENGL_API)
+            .wrap_err("eglBindAPI(EGL_OPENGL_API) failed")?;
+        let egl_context = egl
+            .create_context(egl_display, config, None, &[egl::NONE])
+            .wrap_err("eglCreateContext failed")?;
+        let egl_surface = unsafe {
+            egl.create_window_surface(egl_display, config, window as egl::NativeWindowType, None)
+        }
+        .wrap_err("eglCreateWindowSurface failed")?;
+        egl.make_current(
+            egl_display,
+            Some(egl_surface),
+            Some(egl_surface),
+            Some(egl_context),
+        )
+        .wrap_err("eglMakeCurrent failed")?;
+
+        let mut gr_context = DirectContext::new_gl(None, None)
+            .ok_or_else(|| eyre::eyre!("skia GrContext::new failed"))?;
+        let surface = Self::make_surface(&mut gr_context, geometry)?;
+
+        Ok(SkiaRenderer {
+            surface,
+            gr_context,
+            egl,
+            egl_display,
+            egl_context,
+            egl_surface,
+            display,
+            geometry,
+            title_font,
+            body_font,
+            title_typeface,
+            body_typeface,
+        })
+    }
+
+    fn make_surface(gr_context: &mut DirectContext, geometry: Geometry) -> eyre::Result<Surface> {
+        let fb_info = FramebufferInfo {
+            fboid: 0,
+            format: skia_safe::gpu::gl::Format::RGBA8.into(),
+        };
+        let render_target = BackendRenderTarget::new_gl(
+            (geometry.width as i32, geometry.height as i32),
+            None,
+            8,
+            fb_info,
+        );
+        Surface::from_backend_render_target(
+            gr_context,
+            &render_target,
+            SurfaceOrigin::BottomLeft,
+            skia_safe::ColorType::RGBA8888,
+            None,
+            None,
+        )
+        .ok_or_else(|| eyre::eyre!("failed to wrap the EGL surface as a skia Surface"))
+    }
+
+    /// Recreates the GPU surface at a new size; called when the overlay window is resized.
+    pub fn resize(&mut self, geometry: Geometry) -> eyre::Result<()> {
+        self.surface = Self::make_surface(&mut self.gr_context, geometry)?;
+        self.geometry = geometry;
+        Ok(())
+    }
+}
+
+/// Loads the same font file [`Font`] shapes with as a [`Typeface`] skia can actually rasterize;
+/// `rustybuzz` and `skia_safe` each need their own parse of the font data, since shaping and
+/// drawing go through different libraries.
+pub(crate) fn load_font_pair(path: &FsPath) -> eyre::Result<(Font, Typeface)> {
+    let font = Font::load(path)?;
+    let data =
+        std::fs::read(path).wrap_err_with(|| format!("failed to read font file {:?}", path))?;
+    let typeface = skia_safe::FontMgr::default()
+        .new_from_data(&data, None)
+        .ok_or_else(|| eyre::eyre!("skia could not parse font file {:?}", path))?;
+    Ok((font, typeface))
+}
+
+fn font_for<'a>(
+    size: Size,
+    title_font: &'a Font,
+    title_typeface: &'a Typeface,
+    body_font: &'a Font,
+    body_typeface: &'a Typeface,
+) -> (&'a Font, &'a Typeface) {
+    match size {
+        Size::Normal => (body_font, body_typeface),
+        Size::Large => (title_font, title_typeface),
+    }
+}
+
+fn color_paint(color: &crate::graphics_data::Color, style: PaintStyle) -> Paint {
+    let mut paint = Paint::new(
+        Color4f::new(
+            f32::from(color.red) / 255.0,
+            f32::from(color.green) / 255.0,
+            f32::from(color.blue) / 255.0,
+            f32::from(color.alpha) / 255.0,
+        ),
+        None,
+    );
+    paint.set_style(style);
+    paint.set_anti_alias(true);
+    paint
+}
+
+/// Draws whatever live graphics intersect `dirty` into `canvas`; shared by the GPU
+/// ([`SkiaRenderer`]) and offscreen ([`super::headless::HeadlessRenderer`]) backends, which
+/// differ only in how the canvas's surface reaches the screen (or doesn't) and in how much of
+/// `canvas` they erase before calling this. An empty `dirty` draws nothing.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_graphics(
+    canvas: &mut Canvas,
+    graphics: &HashMap<(usize, String), Option<Graphic>>,
+    dirty: &[DamageRect],
+    width: u32,
+    height: u32,
+    title_font: &Font,
+    title_typeface: &Typeface,
+    body_font: &Font,
+    body_typeface: &Typeface,
+) {
+    if dirty.is_empty() {
+        return;
+    }
+    for (_, graphic) in graphics.iter() {
+        let graphic = match graphic.as_ref() {
+            Some(graphic) => graphic,
+            None => continue,
+        };
+        let drawable = match graphic.drawable.as_ref() {
+            Some(drawable) => drawable,
+            // `GraphicStore` removes an id on a delete-on-arrival command (no drawable) rather
+            // than keeping it, so this only skips entries from some other source.
+            None => continue,
+        };
+        let bbox = drawable_bbox(drawable, width, height, title_font, body_font);
+        if !dirty.iter().any(|d| d.intersects(&bbox)) {
+            continue;
+        }
+        match drawable {
+            Drawable::Rectangle {
+                shape: _,
+                x,
+                y,
+                w,
+                h,
+                fill,
+                color,
+            } => {
+                let rect = Rect::from_xywh(
+                    scale_x(*x, width) as f32,
+                    scale_y(*y, height) as f32,
+                    scale_w(*w, width) as f32,
+                    scale_h(*h, height) as f32,
+                );
+                canvas.draw_rect(rect, &color_paint(fill, PaintStyle::Fill));
+                canvas.draw_rect(rect, &color_paint(color, PaintStyle::Stroke));
+            }
+            Drawable::Vector {
+                shape: _,
+                color,
+                vector,
+            } => {
+                let mut path = Path::new();
+                for (i, point) in vector.iter().enumerate() {
+                    let p = SkPoint::new(
+                        scale_x(point.x, width) as f32,
+                        scale_y(point.y, height) as f32,
+                    );
+                    if i == 0 {
+                        path.move_to(p);
+                    } else {
+                        path.line_to(p);
+                    }
+                }
+                canvas.draw_path(&path, &color_paint(color, PaintStyle::Stroke));
+            }
+            Drawable::Circle {
+                shape: _,
+                x,
+                y,
+                radius,
+                fill,
+                color,
+            } => {
+                let left = x.saturating_sub(*radius);
+                let top = y.saturating_sub(*radius);
+                let rect = Rect::from_xywh(
+                    scale_x(left, width) as f32,
+                    scale_y(top, height) as f32,
+                    scale_w(radius * 2, width) as f32,
+                    scale_h(radius * 2, height) as f32,
+                );
+                canvas.draw_oval(rect, &color_paint(fill, PaintStyle::Fill));
+                canvas.draw_oval(rect, &color_paint(color, PaintStyle::Stroke));
+            }
+            Drawable::Ellipse {
+                shape: _,
+                x,
+                y,
+                rx,
+                ry,
+                fill,
+                color,
+            } => {
+                let left = x.saturating_sub(*rx);
+                let top = y.saturating_sub(*ry);
+                let rect = Rect::from_xywh(
+                    scale_x(left, width) as f32,
+                    scale_y(top, height) as f32,
+                    scale_w(rx * 2, width) as f32,
+                    scale_h(ry * 2, height) as f32,
+                );
+                canvas.draw_oval(rect, &color_paint(fill, PaintStyle::Fill));
+                canvas.draw_oval(rect, &color_paint(color, PaintStyle::Stroke));
+            }
+            Drawable::Line {
+                shape: _,
+                x1,
+                y1,
+                x2,
+                y2,
+                width: line_width,
+                color,
+            } => {
+                let mut paint = color_paint(color, PaintStyle::Stroke);
+                paint.set_stroke_width(*line_width as f32);
+                canvas.draw_line(
+                    SkPoint::new(scale_x(*x1, width) as f32, scale_y(*y1, height) as f32),
+                    SkPoint::new(scale_x(*x2, width) as f32, scale_y(*y2, height) as f32),
+                    &paint,
+                );
+            }
+            Drawable::Text {
+                text,
+                size,
+                size_px,
+                color,
+                x,
+                y,
+            } => {
+                let (shaper, typeface) =
+                    font_for(*size, title_font, title_typeface, body_font, body_typeface);
+                let size_px = size_px.unwrap_or_else(|| size.default_px());
+                let glyphs = shaper.shape(text, size_px);
+                if glyphs.is_empty() {
+                    continue;
+                }
+                let sk_font = SkFont::new(typeface.clone(), size_px);
+                let mut blob_builder = TextBlobBuilder::new();
+                let (glyph_ids, points) = blob_builder.alloc_run_pos(&sk_font, glyphs.len(), None);
+                let mut pen = SkPoint::new(0.0, 0.0);
+                for (i, glyph) in glyphs.iter().enumerate() {
+                    glyph_ids[i] = glyph.glyph_id;
+                    points[i] = SkPoint::new(pen.x + glyph.x_offset, pen.y - glyph.y_offset);
+                    pen.x += glyph.x_advance;
+                    pen.y -= glyph.y_advance;
+                }
+                if let Some(blob) = blob_builder.make() {
+                    canvas.draw_text_blob(
+                        &blob,
+                        (scale_x(*x, width) as f32, scale_y(*y, height) as f32),
+                        &color_paint(color, PaintStyle::Fill),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Renderer for SkiaRenderer {
+    #[instrument(skip(self, graphics))]
+    fn draw(
+        &mut self,
+        graphics: &HashMap<(usize, String), Option<Graphic>>,
+        dirty: &[DamageRect],
+    ) -> eyre::Result<()> {
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        let width = self.geometry.width;
+        let height = self.geometry.height;
+        let canvas = self.surface.canvas();
+        canvas.clear(skia_safe::Color::TRANSPARENT);
+        // The EGL surface double-buffers with undefined back-buffer contents after a swap, so
+        // (unlike the offscreen backend) every visible graphic has to be redrawn whenever
+        // *anything* changed; `dirty` only gates whether this frame does any work at all.
+        let full_window = [DamageRect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }];
+        draw_graphics(
+            canvas,
+            graphics,
+            &full_window,
+            width,
+            height,
+            &self.title_font,
+            &self.title_typeface,
+            &self.body_font,
+            &self.body_typeface,
+        );
+
+        self.gr_context.flush_and_submit();
+        self.egl
+            .swap_buffers(self.egl_display, self.egl_surface)
+            .wrap_err("eglSwapBuffers failed")?;
+        Ok(())
+    }
+
+    fn bbox(&self, graphic: &Graphic) -> DamageRect {
+        drawable_bbox(
+            graphic.drawable.as_ref().unwrap(),
+            self.geometry.width,
+            self.geometry.height,
+            &self.title_font,
+            &self.body_font,
+        )
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.display.conn.as_raw_fd())
+    }
+
+    fn handle_events(
+        &mut self,
+        graphics: &HashMap<(usize, String), Option<Graphic>>,
+    ) -> eyre::Result<()> {
+        while let Some(event) = x11::poll_event(&self.display)? {
+            match event {
+                Event::Expose(_) => {
+                    let full_window = [DamageRect {
+                        x: 0,
+                        y: 0,
+                        width: self.geometry.width,
+                        height: self.geometry.height,
+                    }];
+                    self.draw(graphics, &full_window)?;
+                }
+                Event::ConfigureNotify(ev) => {
+                    self.resize(Geometry {
+                        width: u32::from(ev.width),
+                        height: u32::from(ev.height),
+                    })?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SkiaRenderer {
+    fn drop(&mut self) {
+        let _ = self.egl.destroy_surface(self.egl_display, self.egl_surface);
+        let _ = self.egl.destroy_context(self.egl_display, self.egl_context);
+    }
+}
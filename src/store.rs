@@ -0,0 +1,206 @@
+//! A TTL-based store for live [`Graphic`]s, keyed by `(client_id, Graphic::id)`, so callers don't
+//! have to reimplement expiry bookkeeping themselves: insert a [`Graphic`], periodically [`reap`]
+//! whatever has timed out, and read back whatever's still live with [`active`].
+//!
+//! [`reap`]: GraphicStore::reap
+//! [`active`]: GraphicStore::active
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::graphics_data::Graphic;
+
+/// Identifies one entry: the client that sent it, and the id it sent the `Graphic` under.
+type Key = (usize, String);
+
+struct Entry {
+    graphic: Graphic,
+    /// `None` means this entry persists until explicitly cleared (a `ttl` of 0 or negative).
+    expiry: Option<Instant>,
+}
+
+/// The currently-live set of graphics, keyed by `(client_id, id)`.
+#[derive(Default)]
+pub struct GraphicStore {
+    entries: HashMap<Key, Entry>,
+    /// Insertion order of `entries`' keys, so [`GraphicStore::active`] can yield graphics in the
+    /// order they first appeared rather than hash-map order.
+    order: Vec<Key>,
+}
+
+impl GraphicStore {
+    pub fn new() -> GraphicStore {
+        GraphicStore::default()
+    }
+
+    /// Inserts or replaces `client_id`'s `graphic`, recording an expiry of `now + ttl` seconds for
+    /// a positive `ttl` (otherwise it persists until cleared); a `graphic` with no drawable (a
+    /// delete-on-arrival command) removes its id instead.
+    pub fn insert(&mut self, client_id: usize, graphic: Graphic) {
+        let key = (client_id, graphic.id.clone());
+        if graphic.drawable.is_none() {
+            self.remove(&key);
+            return;
+        }
+        let expiry = if graphic.ttl > 0 {
+            Some(Instant::now() + Duration::from_secs(graphic.ttl as u64))
+        } else {
+            None
+        };
+        if !self.entries.contains_key(&key) {
+            self.order.push(key.clone());
+        }
+        self.entries.insert(key, Entry { graphic, expiry });
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if self.entries.remove(key).is_some() {
+            self.order.retain(|existing| existing != key);
+        }
+    }
+
+    /// Drops every entry whose expiry has passed as of `now`, returning the keys removed so the
+    /// caller can clear whatever they last drew for them.
+    pub fn reap(&mut self, now: Instant) -> Vec<Key> {
+        let expired: Vec<Key> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expiry.map_or(false, |expiry| expiry <= now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.remove(key);
+        }
+        expired
+    }
+
+    /// The currently-live graphics, in the order their keys were first inserted.
+    pub fn active(&self) -> impl Iterator<Item = (&Key, &Graphic)> {
+        self.order
+            .iter()
+            .filter_map(move |key| self.entries.get(key).map(|entry| (key, &entry.graphic)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics_data::{Color, Drawable, ShapeCircle};
+
+    fn graphic(id: &str, ttl: isize) -> Graphic {
+        Graphic {
+            id: id.to_owned(),
+            ttl,
+            snapshot: None,
+            drawable: Some(Drawable::Circle {
+                shape: ShapeCircle::Circle,
+                x: 0,
+                y: 0,
+                radius: 1,
+                fill: Color {
+                    red: 0,
+                    green: 0,
+                    blue: 0,
+                    alpha: 255,
+                },
+                color: Color {
+                    red: 0,
+                    green: 0,
+                    blue: 0,
+                    alpha: 255,
+                },
+            }),
+        }
+    }
+
+    fn deleted(id: &str) -> Graphic {
+        Graphic {
+            id: id.to_owned(),
+            ttl: 0,
+            snapshot: None,
+            drawable: None,
+        }
+    }
+
+    #[test]
+    fn active_yields_nothing_for_an_empty_store() {
+        let store = GraphicStore::new();
+        assert_eq!(store.active().count(), 0);
+    }
+
+    #[test]
+    fn insert_then_active_yields_the_graphic() {
+        let mut store = GraphicStore::new();
+        store.insert(0, graphic("a", -1));
+        let active: Vec<_> = store.active().collect();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].0, &(0, "a".to_owned()));
+        assert_eq!(active[0].1.id, "a");
+    }
+
+    #[test]
+    fn active_preserves_insertion_order() {
+        let mut store = GraphicStore::new();
+        store.insert(0, graphic("first", -1));
+        store.insert(0, graphic("second", -1));
+        store.insert(0, graphic("third", -1));
+        let ids: Vec<&str> = store.active().map(|(_, g)| g.id.as_str()).collect();
+        assert_eq!(ids, ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn reinserting_the_same_key_keeps_its_original_position() {
+        let mut store = GraphicStore::new();
+        store.insert(0, graphic("first", -1));
+        store.insert(0, graphic("second", -1));
+        store.insert(0, graphic("first", -1));
+        let ids: Vec<&str> = store.active().map(|(_, g)| g.id.as_str()).collect();
+        assert_eq!(ids, ["first", "second"]);
+    }
+
+    #[test]
+    fn different_clients_can_share_an_id() {
+        let mut store = GraphicStore::new();
+        store.insert(0, graphic("a", -1));
+        store.insert(1, graphic("a", -1));
+        assert_eq!(store.active().count(), 2);
+    }
+
+    #[test]
+    fn inserting_a_graphic_with_no_drawable_removes_it() {
+        let mut store = GraphicStore::new();
+        store.insert(0, graphic("a", -1));
+        store.insert(0, deleted("a"));
+        assert_eq!(store.active().count(), 0);
+    }
+
+    #[test]
+    fn nonpositive_ttl_persists_until_explicitly_cleared() {
+        let mut store = GraphicStore::new();
+        store.insert(0, graphic("a", 0));
+        store.insert(0, graphic("b", -1));
+        assert_eq!(
+            store.reap(Instant::now() + Duration::from_secs(3600)).len(),
+            0
+        );
+        assert_eq!(store.active().count(), 2);
+    }
+
+    #[test]
+    fn reap_drops_entries_whose_expiry_has_passed() {
+        let mut store = GraphicStore::new();
+        store.insert(0, graphic("expires", 1));
+        store.insert(0, graphic("persists", -1));
+        let reaped = store.reap(Instant::now() + Duration::from_secs(2));
+        assert_eq!(reaped, vec![(0, "expires".to_owned())]);
+        assert_eq!(store.active().count(), 1);
+    }
+
+    #[test]
+    fn reap_leaves_unexpired_entries_alone() {
+        let mut store = GraphicStore::new();
+        store.insert(0, graphic("a", 3600));
+        assert_eq!(store.reap(Instant::now()).len(), 0);
+        assert_eq!(store.active().count(), 1);
+    }
+}
@@ -0,0 +1,260 @@
+//! Renders a batch of [`Graphic`]s to a self-contained SVG document, so a scene can be inspected
+//! or golden-file tested without an X server or a GPU: compare the generated markup against a
+//! committed reference, or open it straight in a browser to preview what the overlay would show.
+
+use crate::graphics_data::{Drawable, Graphic, Marker, Size};
+
+/// The half-length of the cross drawn at a [`Marker::Cross`] vector point, in SVG user units.
+const CROSS_RADIUS: f32 = 4.0;
+/// The radius of the circle drawn at a [`Marker::Circle`] vector point, in SVG user units.
+const CIRCLE_RADIUS: f32 = 4.0;
+/// The half side-length of the square drawn at a [`Marker::Square`] vector point.
+const SQUARE_HALF_SIDE: f32 = 4.0;
+/// Half the width (and half the height) of the triangle drawn at a [`Marker::Triangle`] vector
+/// point: apex this far above the point, base corners this far below and to either side.
+const TRIANGLE_RADIUS: f32 = 5.0;
+
+/// Renders `graphics` into an SVG document `width` x `height` user units, in the coordinates
+/// clients send (unlike the X11/Skia backends, no `scale_*` is applied, so the output matches what
+/// a client sent, not what a particular window size would stretch it to).
+pub fn render_svg(graphics: &[Graphic], width: u32, height: u32) -> String {
+    let mut body = String::new();
+    for graphic in graphics {
+        if let Some(drawable) = &graphic.drawable {
+            body.push_str(&render_drawable(drawable));
+        }
+    }
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         viewBox=\"0 0 {} {}\">\n{}</svg>\n",
+        width, height, width, height, body
+    )
+}
+
+fn render_drawable(drawable: &Drawable) -> String {
+    match drawable {
+        Drawable::Rectangle {
+            shape: _,
+            x,
+            y,
+            w,
+            h,
+            fill,
+            color,
+        } => format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\" />\n",
+            x,
+            y,
+            w,
+            h,
+            String::from(fill.clone()),
+            String::from(color.clone())
+        ),
+        Drawable::Text {
+            text,
+            size,
+            size_px,
+            color,
+            x,
+            y,
+        } => {
+            let size_px = size_px.unwrap_or_else(|| size.default_px());
+            format!(
+                "<text x=\"{}\" y=\"{}\" font-size=\"{}\" fill=\"{}\">{}</text>\n",
+                x,
+                y,
+                size_px,
+                String::from(color.clone()),
+                escape_xml(text)
+            )
+        }
+        Drawable::Circle {
+            shape: _,
+            x,
+            y,
+            radius,
+            fill,
+            color,
+        } => format!(
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" stroke=\"{}\" />\n",
+            x,
+            y,
+            radius,
+            String::from(fill.clone()),
+            String::from(color.clone())
+        ),
+        Drawable::Ellipse {
+            shape: _,
+            x,
+            y,
+            rx,
+            ry,
+            fill,
+            color,
+        } => format!(
+            "<ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\" fill=\"{}\" stroke=\"{}\" />\n",
+            x,
+            y,
+            rx,
+            ry,
+            String::from(fill.clone()),
+            String::from(color.clone())
+        ),
+        Drawable::Line {
+            shape: _,
+            x1,
+            y1,
+            x2,
+            y2,
+            width,
+            color,
+        } => format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke-width=\"{}\" stroke=\"{}\" />\n",
+            x1,
+            y1,
+            x2,
+            y2,
+            width,
+            String::from(color.clone())
+        ),
+        Drawable::Vector {
+            shape: _,
+            color,
+            vector,
+        } => {
+            let points: String = vector
+                .iter()
+                .map(|p| format!("{},{}", p.x, p.y))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let mut svg = format!(
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" />\n",
+                points,
+                String::from(color.clone())
+            );
+            for point in vector {
+                let marker_color = String::from(point.color.clone());
+                match point.marker {
+                    Marker::Circle => svg.push_str(&format!(
+                        "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" />\n",
+                        point.x, point.y, CIRCLE_RADIUS, marker_color
+                    )),
+                    Marker::Cross => {
+                        let (x, y) = (point.x as f32, point.y as f32);
+                        svg.push_str(&format!(
+                            "<path d=\"M {} {} L {} {} M {} {} L {} {}\" stroke=\"{}\" />\n",
+                            x - CROSS_RADIUS,
+                            y - CROSS_RADIUS,
+                            x + CROSS_RADIUS,
+                            y + CROSS_RADIUS,
+                            x - CROSS_RADIUS,
+                            y + CROSS_RADIUS,
+                            x + CROSS_RADIUS,
+                            y - CROSS_RADIUS,
+                            marker_color
+                        ));
+                    }
+                    Marker::Square => {
+                        let (x, y) = (point.x as f32, point.y as f32);
+                        svg.push_str(&format!(
+                            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" />\n",
+                            x - SQUARE_HALF_SIDE,
+                            y - SQUARE_HALF_SIDE,
+                            SQUARE_HALF_SIDE * 2.0,
+                            SQUARE_HALF_SIDE * 2.0,
+                            marker_color
+                        ));
+                    }
+                    Marker::Triangle => {
+                        let (x, y) = (point.x as f32, point.y as f32);
+                        svg.push_str(&format!(
+                            "<polygon points=\"{},{} {},{} {},{}\" fill=\"{}\" />\n",
+                            x,
+                            y - TRIANGLE_RADIUS,
+                            x - TRIANGLE_RADIUS,
+                            y + TRIANGLE_RADIUS,
+                            x + TRIANGLE_RADIUS,
+                            y + TRIANGLE_RADIUS,
+                            marker_color
+                        ));
+                    }
+                }
+            }
+            svg
+        }
+    }
+}
+
+/// Escapes the handful of characters that are special inside SVG text content.
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics_data::ShapeRect;
+
+    fn color(s: &str) -> crate::graphics_data::Color {
+        s.to_owned().try_into().unwrap()
+    }
+
+    #[test]
+    fn escape_xml_escapes_special_characters() {
+        assert_eq!(
+            escape_xml(r#"<tag a="b"> & text"#),
+            "&lt;tag a=&quot;b&quot;&gt; &amp; text"
+        );
+    }
+
+    #[test]
+    fn escape_xml_leaves_plain_text_alone() {
+        assert_eq!(escape_xml("plain text"), "plain text");
+    }
+
+    #[test]
+    fn render_drawable_rectangle() {
+        let rect = Drawable::Rectangle {
+            shape: ShapeRect::Rect,
+            x: 1,
+            y: 2,
+            w: 3,
+            h: 4,
+            fill: color("#ff0000"),
+            color: color("#00ff00"),
+        };
+        assert_eq!(
+            render_drawable(&rect),
+            "<rect x=\"1\" y=\"2\" width=\"3\" height=\"4\" fill=\"#ff0000\" stroke=\"#00ff00\" />\n"
+        );
+    }
+
+    #[test]
+    fn render_svg_wraps_body_in_a_sized_document() {
+        let document = render_svg(&[], 1280, 1024);
+        assert_eq!(
+            document,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1280\" height=\"1024\" \
+             viewBox=\"0 0 1280 1024\">\n</svg>\n"
+        );
+    }
+
+    #[test]
+    fn render_svg_skips_graphics_with_no_drawable() {
+        let graphic = Graphic {
+            id: "deleted".to_owned(),
+            ttl: 0,
+            drawable: None,
+            snapshot: None,
+        };
+        let document = render_svg(&[graphic], 100, 100);
+        assert_eq!(
+            document,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"100\" height=\"100\" \
+             viewBox=\"0 0 100 100\">\n</svg>\n"
+        );
+    }
+}